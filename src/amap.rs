@@ -0,0 +1,572 @@
+use colored::*;
+use reqwest::Client;
+use serde::{Deserialize, Deserializer, Serialize};
+use actix_web::http::StatusCode;
+use serde_json::Value;
+use std::{error::Error, fmt, time::Duration};
+
+use crate::config::{Config, AMAP_MAX_PAGE};
+use crate::hours::OpenState;
+use crate::i18n::{Lang, Msg};
+use crate::poi_types::{self, PoiCategory};
+use crate::retry::{parse_retry_after, RetryPolicy, Transient};
+
+// 高德周边搜索 /v3/place/around 的响应
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PoiSearchResponse {
+    #[serde(default, deserialize_with = "de_u32")]
+    pub count: u32,
+    #[serde(default)]
+    pub pois: Vec<Poi>,
+}
+
+// 规范化后的POI：空字段统一为 null，距离为数字，坐标拆分为经纬度
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Poi {
+    #[serde(default, deserialize_with = "de_string")]
+    pub id: String,
+    #[serde(default, deserialize_with = "de_string")]
+    pub name: String,
+    #[serde(rename = "type", default, deserialize_with = "de_string")]
+    pub poi_type: String,      // 例如 "餐饮服务;中餐厅;火锅店"
+    #[serde(default, deserialize_with = "de_string")]
+    pub typecode: String,
+    #[serde(default, deserialize_with = "de_opt_string")]
+    pub address: Option<String>,
+    #[serde(default, deserialize_with = "de_opt_string")]
+    pub tel: Option<String>,
+    #[serde(default, deserialize_with = "de_opt_u32")]
+    pub distance: Option<u32>, // 米
+    #[serde(default, deserialize_with = "de_location")]
+    pub location: Option<Coordinates>,
+    #[serde(default, deserialize_with = "de_opt_string")]
+    pub pname: Option<String>,
+    #[serde(default, deserialize_with = "de_opt_string")]
+    pub cityname: Option<String>,
+    #[serde(default, deserialize_with = "de_opt_string")]
+    pub adname: Option<String>,
+    #[serde(default, deserialize_with = "de_biz_ext")]
+    pub biz_ext: BizExt,
+    // 以下字段只有 extensions=all 或详情接口才有值
+    #[serde(default, deserialize_with = "de_opt_string")]
+    pub business_area: Option<String>,   // 所属商圈
+    #[serde(default, alias = "tag", deserialize_with = "de_tags")]
+    pub tags: Vec<String>,               // 高德的 tag 字段，如 "招牌菜,特色菜"，拆分为列表
+    #[serde(default, deserialize_with = "de_photos")]
+    pub photos: Vec<Photo>,
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub category: Option<PoiCategory>,   // typecode 对应的分类名称，见 poi_types::annotate
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub open_state: Option<OpenState>,   // 请求了 open_now / open_at 时按营业时间判断的营业状态
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Coordinates {
+    pub longitude: f64,
+    pub latitude: f64,
+}
+
+// 商业扩展信息，只有 extensions=all 时才有值
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct BizExt {
+    #[serde(default, deserialize_with = "de_opt_f32")]
+    pub rating: Option<f32>,
+    #[serde(default, deserialize_with = "de_opt_f32")]
+    pub cost: Option<f32>,     // 人均消费（元）
+    #[serde(default, deserialize_with = "de_opt_string")]
+    pub open_time: Option<String>,   // 营业时间，如 "10:00-22:00"
+    // 部分 POI 只在 opentime2 中给出营业时间，反序列化后合并到 open_time
+    #[serde(default, skip_serializing, deserialize_with = "de_opt_string")]
+    opentime2: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Photo {
+    #[serde(default, deserialize_with = "de_opt_string")]
+    pub title: Option<String>,
+    #[serde(default, deserialize_with = "de_string")]
+    pub url: String,
+}
+
+// 高德对空字段返回 [] 而不是空字符串或 null，这里统一转为 None
+fn de_opt_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Ok(match Value::deserialize(deserializer)? {
+        Value::String(s) if !s.trim().is_empty() => Some(s),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    })
+}
+
+fn de_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    Ok(de_opt_string(deserializer)?.unwrap_or_default())
+}
+
+// 数字字段以字符串形式返回，例如 "distance": "355"
+fn de_opt_u32<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    Ok(de_opt_f32(deserializer)?.map(|n| n.round() as u32))
+}
+
+fn de_u32<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    Ok(de_opt_u32(deserializer)?.unwrap_or_default())
+}
+
+fn de_opt_f32<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f32>, D::Error> {
+    Ok(match Value::deserialize(deserializer)? {
+        Value::String(s) => s.trim().parse::<f32>().ok(),
+        Value::Number(n) => n.as_f64().map(|n| n as f32),
+        _ => None,
+    })
+}
+
+// 坐标格式 "经度,纬度"；序列化后直接输出对象，便于重复反序列化
+fn de_location<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Coordinates>, D::Error> {
+    Ok(match Value::deserialize(deserializer)? {
+        Value::String(s) => parse_coordinates(&s),
+        value @ Value::Object(_) => serde_json::from_value(value).ok(),
+        _ => None,
+    })
+}
+
+// extensions=base 时 biz_ext 为 []
+fn de_biz_ext<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BizExt, D::Error> {
+    Ok(match Value::deserialize(deserializer)? {
+        value @ Value::Object(_) => {
+            let mut biz_ext: BizExt = serde_json::from_value(value).unwrap_or_default();
+            biz_ext.open_time = biz_ext.open_time.or(biz_ext.opentime2.take());
+            biz_ext
+        }
+        _ => BizExt::default(),
+    })
+}
+
+// 高德的 tag 为逗号或分号分隔的字符串；序列化后为数组
+fn de_tags<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    Ok(match Value::deserialize(deserializer)? {
+        Value::String(s) => s
+            .split([',', ';', '，', '；'])
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_string)
+            .collect(),
+        value @ Value::Array(_) => serde_json::from_value(value).unwrap_or_default(),
+        _ => Vec::new(),
+    })
+}
+
+// 没有 url 的照片直接丢弃
+fn de_photos<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Photo>, D::Error> {
+    let photos: Vec<Photo> = match Value::deserialize(deserializer)? {
+        value @ Value::Array(_) => serde_json::from_value(value).unwrap_or_default(),
+        _ => Vec::new(),
+    };
+    Ok(photos.into_iter().filter(|photo| !photo.url.is_empty()).collect())
+}
+
+pub fn parse_coordinates(location: &str) -> Option<Coordinates> {
+    let (longitude, latitude) = location.split_once(',')?;
+    Some(Coordinates {
+        longitude: longitude.trim().parse().ok()?,
+        latitude: latitude.trim().parse().ok()?,
+    })
+}
+
+// 高德API错误：HTTP 200 但响应体 status 为 "0" 时按 infocode 分类
+#[derive(Debug)]
+pub enum AmapError {
+    InvalidKey { infocode: String, info: String },          // 密钥无效、无权限、绑定不匹配
+    QuotaExceeded { infocode: String, info: String },       // 日配额用尽
+    QpsLimit { infocode: String, info: String },            // 并发/频率超限
+    InvalidParams { infocode: String, info: String },       // 请求参数错误
+    ServiceUnavailable { infocode: String, info: String },  // 高德服务端繁忙或故障
+    Http { status: u16, retry_after: Option<Duration> },
+    Network(String),
+    InvalidResponse(String),
+}
+
+impl AmapError {
+    // 参考高德错误码说明 https://lbs.amap.com/api/webservice/guide/tools/info
+    fn from_infocode(infocode: &str, info: &str) -> Self {
+        let (infocode, info) = (infocode.to_string(), info.to_string());
+        match infocode.as_str() {
+            "10001" | "10002" | "10005" | "10006" | "10007" | "10008" | "10009" | "10012" | "10013"
+            | "10026" | "10041" => AmapError::InvalidKey { infocode, info },
+            "10003" | "10010" | "10029" | "10044" | "10045" => AmapError::QuotaExceeded { infocode, info },
+            "10004" | "10014" | "10019" | "10020" | "10021" => AmapError::QpsLimit { infocode, info },
+            code if code.starts_with("200") && code != "20003" => AmapError::InvalidParams { infocode, info },
+            _ => AmapError::ServiceUnavailable { infocode, info },
+        }
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            AmapError::InvalidKey { .. } => StatusCode::UNAUTHORIZED,
+            AmapError::QuotaExceeded { .. } | AmapError::QpsLimit { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AmapError::InvalidParams { .. } => StatusCode::BAD_REQUEST,
+            _ => StatusCode::BAD_GATEWAY,
+        }
+    }
+}
+
+impl AmapError {
+    // 按语言生成的错误说明，文本来自消息目录的 amap 段
+    pub fn localized(&self, lang: Lang) -> String {
+        let coded = |key: &'static str, infocode: &str, info: &str| {
+            Msg::new(key).arg("infocode", infocode).arg("info", info).text(lang)
+        };
+        match self {
+            AmapError::InvalidKey { infocode, info } => coded("amap.invalid_key", infocode, info),
+            AmapError::QuotaExceeded { infocode, info } => coded("amap.quota_exceeded", infocode, info),
+            AmapError::QpsLimit { infocode, info } => coded("amap.qps_limit", infocode, info),
+            AmapError::InvalidParams { infocode, info } => coded("amap.invalid_params", infocode, info),
+            AmapError::ServiceUnavailable { infocode, info } => coded("amap.service_unavailable", infocode, info),
+            AmapError::Http { status, .. } => Msg::new("amap.http").arg("status", status).text(lang),
+            AmapError::Network(e) => Msg::new("amap.network").arg("detail", e).text(lang),
+            AmapError::InvalidResponse(e) => Msg::new("amap.invalid_response").arg("detail", e).text(lang),
+        }
+    }
+}
+
+impl fmt::Display for AmapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.localized(Lang::ZhCn))
+    }
+}
+
+impl Error for AmapError {}
+
+// 网络错误、HTTP 5xx/429 和并发超限可以重试；密钥、配额、参数错误重试也不会成功
+impl Transient for AmapError {
+    fn is_transient(&self) -> bool {
+        match self {
+            AmapError::Network(_) | AmapError::QpsLimit { .. } => true,
+            AmapError::Http { status, .. } => *status == 429 || *status >= 500,
+            _ => false,
+        }
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        match self {
+            AmapError::Http { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for AmapError {
+    fn from(e: reqwest::Error) -> Self {
+        // 请求URL中带有 key，不能出现在错误信息里
+        let e = e.without_url();
+        if e.is_decode() {
+            AmapError::InvalidResponse(e.to_string())
+        } else {
+            AmapError::Network(e.to_string())
+        }
+    }
+}
+
+impl From<serde_json::Error> for AmapError {
+    fn from(e: serde_json::Error) -> Self {
+        AmapError::InvalidResponse(e.to_string())
+    }
+}
+
+// 调用高德Web服务API，临时故障按重试策略重试
+pub async fn get_json(
+    client: &Client,
+    retry: &RetryPolicy,
+    endpoint: &str,
+    params: &[(&str, &str)],
+    user_agent: &str,
+) -> Result<Value, AmapError> {
+    let call = format!("高德{}", endpoint.trim_start_matches("https://restapi.amap.com"));
+    retry.run(&call, || get_json_once(client, endpoint, params, user_agent)).await
+}
+
+// 单次调用，检查HTTP状态和响应体中的 status/infocode
+async fn get_json_once(client: &Client, endpoint: &str, params: &[(&str, &str)], user_agent: &str) -> Result<Value, AmapError> {
+    let response = client.get(endpoint)
+        .query(params)
+        .header("User-Agent", user_agent)
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(AmapError::Http {
+            status: response.status().as_u16(),
+            retry_after: parse_retry_after(response.headers()),
+        });
+    }
+
+    let body = response.text().await?;
+    let data: Value = serde_json::from_str(&body)?;
+
+    if data["status"].as_str() != Some("1") {
+        let infocode = data["infocode"].as_str().unwrap_or_default();
+        let info = data["info"].as_str().unwrap_or_default();
+        return Err(AmapError::from_infocode(infocode, info));
+    }
+    Ok(data)
+}
+
+// 高德返回字段的详细程度：base 只有基本信息，all 额外包含 biz_ext（评分、人均消费）等扩展信息
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Extensions {
+    #[default]
+    Base,
+    All,
+}
+
+impl Extensions {
+    pub fn parse(name: &str) -> Option<Extensions> {
+        match name.trim() {
+            "base" => Some(Extensions::Base),
+            "all" => Some(Extensions::All),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Extensions::Base => "base",
+            Extensions::All => "all",
+        }
+    }
+}
+
+// 分页信息，随响应返回；page 从 1 开始，每页 page_size 条
+#[derive(Serialize, Debug, Clone)]
+pub struct Pagination {
+    pub page: u32,              // 起始页
+    pub page_size: u32,
+    pub pages_fetched: u32,     // 实际请求并合并的页数
+    pub total: u32,             // 高德返回的结果总数
+    pub total_pages: u32,       // 按 page_size 计算的总页数，不超过高德的翻页上限
+    pub has_more: bool,
+    pub next_page: Option<u32>, // "加载更多" 时作为下一次请求的 page
+}
+
+impl Pagination {
+    // exhausted 表示最后一页不满 page_size 条，即没有更多结果
+    fn new(page: u32, page_size: u32, pages_fetched: u32, total: u32, exhausted: bool) -> Self {
+        let total_pages = total.div_ceil(page_size.max(1)).min(AMAP_MAX_PAGE);
+        let fetched_until = page + pages_fetched - 1;
+        let has_more = !exhausted && fetched_until < total_pages;
+        Pagination {
+            page,
+            page_size,
+            pages_fetched,
+            total,
+            total_pages,
+            has_more,
+            next_page: has_more.then_some(fetched_until + 1),
+        }
+    }
+
+    // 本地筛选后按客户端的 page 和 page_size 截取的结果，见 search::search_nearby
+    // total 和 total_pages 仍按筛选前高德的结果数计算，has_more 由筛选后的结果决定
+    pub fn post_filtered(page: u32, page_size: u32, pages: u32, total: u32, has_more: bool) -> Self {
+        Pagination {
+            page,
+            page_size,
+            pages_fetched: pages,
+            total,
+            total_pages: total.div_ceil(page_size.max(1)).min(AMAP_MAX_PAGE),
+            has_more,
+            next_page: has_more.then_some(page + pages),
+        }
+    }
+}
+
+// 从第 page 页开始连续请求最多 pages 页并合并结果（按 id 去重），遇到不满一页的结果时提前结束
+pub async fn search_food_pages(
+    client: &Client,
+    config: &Config,
+    retry: &RetryPolicy,
+    location: (f64, f64),
+    page: u32,
+    pages: u32,
+    extensions: Extensions,
+) -> Result<(PoiSearchResponse, Pagination), AmapError> {
+    let page_size = config.max_food_results;
+    let last_page = page.saturating_add(pages.max(1) - 1).min(AMAP_MAX_PAGE);
+    let mut merged = PoiSearchResponse::default();
+    let mut pages_fetched = 0;
+    let mut exhausted = false;
+    for current in page..=last_page {
+        let data = search_food(client, config, retry, location, current, extensions).await?;
+        pages_fetched += 1;
+        // 高德在后续页偶尔返回 count 为 0，取各页中的最大值
+        merged.count = merged.count.max(data.count);
+        exhausted = (data.pois.len() as u32) < page_size;
+        for poi in data.pois {
+            if !merged.pois.iter().any(|p| p.id == poi.id) {
+                merged.pois.push(poi);
+            }
+        }
+        if exhausted {
+            break;
+        }
+    }
+
+    let pagination = Pagination::new(page, page_size, pages_fetched, merged.count, exhausted);
+    Ok((merged, pagination))
+}
+
+pub async fn search_food(
+    client: &Client,
+    config: &Config,
+    retry: &RetryPolicy,
+    location: (f64, f64),
+    page: u32,
+    extensions: Extensions,
+) -> Result<PoiSearchResponse, AmapError> {
+    let (longitude, latitude) = location;
+    let location_str = format!("{},{}", longitude, latitude);
+    let radius = config.food_radius.to_string();
+    let offset = config.max_food_results.to_string();
+    let page_str = page.to_string();
+    // food_types 可以使用分类名称，校验已在配置加载和请求参数处完成
+    let types = poi_types::resolve_type_codes(&config.food_types).unwrap_or_else(|_| config.food_types.clone());
+
+    println!("\n🍽️  正在搜索附近美食...");
+    println!("📍 中心位置: {}", config.keywords.green());
+    println!("🗺️ 坐标: {:.6}, {:.6}", longitude, latitude);
+    println!("🔍 参数: 半径{}米 | 类型: {} | 关键词: {} | 每页: {} | 第 {} 页",
+             config.food_radius, types, config.food_keywords, config.max_food_results, page);
+
+    let mut params = vec![
+        ("key", config.api_key.as_str()),
+        ("location", location_str.as_str()),
+        ("radius", radius.as_str()),
+        ("offset", offset.as_str()),
+        ("page", page_str.as_str()),
+        ("extensions", extensions.as_str()),
+    ];
+    // 关键词和类型同时提供时高德返回同时满足两者的结果，只给关键词时不限类型
+    if !types.is_empty() {
+        params.push(("types", types.as_str()));
+    }
+    if !config.food_keywords.is_empty() {
+        params.push(("keywords", config.food_keywords.as_str()));
+    }
+    let user_agent = format!("{}-food-service", config.username);
+    let data = get_json(client, retry, "https://restapi.amap.com/v3/place/around", &params, &user_agent).await?;
+    Ok(serde_json::from_value(data)?)
+}
+
+// 高德关键字搜索 /v3/place/text，在 config.city 范围内按 food_keywords 搜索（不按距离），food_types 为空时不限类型
+pub async fn search_text(
+    client: &Client,
+    config: &Config,
+    retry: &RetryPolicy,
+    page: u32,
+    extensions: Extensions,
+) -> Result<(PoiSearchResponse, Pagination), AmapError> {
+    let offset = config.max_food_results.to_string();
+    let page_str = page.to_string();
+    let types = poi_types::resolve_type_codes(&config.food_types).unwrap_or_else(|_| config.food_types.clone());
+
+    println!("\n🔎 正在按关键词搜索: {}", config.food_keywords.green());
+    println!("🔍 参数: 城市: {} | 类型: {} | 每页: {} | 第 {} 页",
+             config.city, types, config.max_food_results, page);
+
+    let mut params = vec![
+        ("key", config.api_key.as_str()),
+        ("keywords", config.food_keywords.as_str()),
+        ("city", config.city.as_str()),
+        ("citylimit", "true"),
+        ("offset", offset.as_str()),
+        ("page", page_str.as_str()),
+        ("extensions", extensions.as_str()),
+    ];
+    if !types.is_empty() {
+        params.push(("types", types.as_str()));
+    }
+    let user_agent = format!("{}-food-service", config.username);
+    let data = get_json(client, retry, "https://restapi.amap.com/v3/place/text", &params, &user_agent).await?;
+    let data: PoiSearchResponse = serde_json::from_value(data)?;
+    let exhausted = (data.pois.len() as u32) < config.max_food_results;
+    let pagination = Pagination::new(page, config.max_food_results, 1, data.count, exhausted);
+    Ok((data, pagination))
+}
+
+// 高德 POI 详情 /v3/place/detail，返回的字段与 extensions=all 的周边搜索相同；id 不存在时返回 None
+pub async fn poi_detail(client: &Client, config: &Config, retry: &RetryPolicy, id: &str) -> Result<Option<Poi>, AmapError> {
+    println!("\n🔎 正在查询 POI 详情: {}", id.green());
+    let params = [
+        ("key", config.api_key.as_str()),
+        ("id", id),
+    ];
+    let user_agent = format!("{}-food-service", config.username);
+    let data = get_json(client, retry, "https://restapi.amap.com/v3/place/detail", &params, &user_agent).await?;
+    let response: PoiSearchResponse = serde_json::from_value(data)?;
+    Ok(response.pois.into_iter().find(|poi| poi.id == id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // 仓库中的 food_results.json 是一次真实的 /v3/place/around 响应（extensions=base）
+    fn fixture() -> PoiSearchResponse {
+        let contents = include_str!("../food_results.json");
+        let value: Value = serde_json::from_str(contents).unwrap();
+        serde_json::from_value(value["food_results"].clone()).unwrap()
+    }
+
+    #[test]
+    fn normalizes_fixture() {
+        let data = fixture();
+        assert_eq!(data.count, 114);
+        assert_eq!(data.pois.len(), 8);
+
+        let first = &data.pois[0];
+        assert_eq!(first.id, "B0IAJASUOY");
+        assert_eq!(first.tel, None);   // 高德返回 []
+        assert_eq!(first.address.as_deref(), Some("图强路321号大连理工大学(开发区校区)憩苑"));
+        assert_eq!(first.distance, Some(355));   // 高德返回 "355"
+        assert_eq!(first.location, Some(Coordinates { longitude: 121.820871, latitude: 39.084226 }));
+        assert_eq!(first.biz_ext.rating, None);   // extensions=base 时 biz_ext 为 []
+        assert_eq!(first.biz_ext.cost, None);
+        assert!(first.tags.is_empty());
+        assert!(first.photos.is_empty());
+
+        assert_eq!(data.pois[1].tel.as_deref(), Some("4000100100"));
+        assert!(data.pois.iter().all(|poi| poi.distance.is_some() && poi.location.is_some()));
+    }
+
+    // 序列化后的结果可以再次反序列化（响应日志和缓存会这样用）
+    #[test]
+    fn round_trips() {
+        let data = fixture();
+        let again: PoiSearchResponse = serde_json::from_value(serde_json::to_value(&data).unwrap()).unwrap();
+        assert_eq!(again.pois[0].location, data.pois[0].location);
+        assert_eq!(again.pois[0].distance, data.pois[0].distance);
+        assert_eq!(again.pois[0].tel, None);
+    }
+
+    #[test]
+    fn normalizes_biz_ext() {
+        let poi: Poi = serde_json::from_value(json!({
+            "id": "B0FFHVE6FT",
+            "name": "示例火锅",
+            "typecode": "050117",
+            "address": [],
+            "distance": [],
+            "biz_ext": { "rating": "4.6", "cost": "98.00", "open_time": [], "opentime2": "10:00-22:00" },
+            "tag": "毛肚，鸭肠；牛油锅底",
+            "photos": [{ "title": [], "url": "http://example.com/1.jpg" }, { "title": "无图", "url": [] }]
+        }))
+        .unwrap();
+        assert_eq!(poi.address, None);
+        assert_eq!(poi.distance, None);
+        assert_eq!(poi.biz_ext.rating, Some(4.6));
+        assert_eq!(poi.biz_ext.cost, Some(98.0));
+        assert_eq!(poi.biz_ext.open_time.as_deref(), Some("10:00-22:00"));
+        assert_eq!(poi.tags, ["毛肚", "鸭肠", "牛油锅底"]);
+        assert_eq!(poi.photos.len(), 1);
+        assert_eq!(poi.photos[0].title, None);
+    }
+}
@@ -0,0 +1,57 @@
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+// 命令行参数，优先级最高（默认值 → 配置文件 → TOSS_* 环境变量 → 命令行）
+// 出于安全考虑，密钥不能通过命令行传入（会出现在进程列表中）
+#[derive(Parser, Debug, Clone, Default)]
+#[command(name = "my_crate_demo", version, about = "智能地理美食推荐系统")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// 配置文件路径，支持 .json / .toml / .yaml / .yml（默认读取 ./config.json）
+    #[arg(short, long, value_name = "PATH", env = "TOSS_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// 密钥文件路径，只允许包含 api_key、qwen_api_key、admin_token 和 llm_fallbacks[].api_key
+    #[arg(long, value_name = "PATH", env = "TOSS_SECRETS_FILE")]
+    pub secrets_file: Option<PathBuf>,
+
+    /// 打印合并后的最终配置（密钥已脱敏）后退出
+    #[arg(long)]
+    pub print_config: bool,
+
+    /// 监听地址，可重复指定多个
+    #[arg(long, value_name = "ADDR")]
+    pub bind: Vec<String>,
+
+    /// 工作线程数
+    #[arg(long)]
+    pub workers: Option<usize>,
+
+    /// 默认城市
+    #[arg(long)]
+    pub city: Option<String>,
+
+    /// 美食搜索半径（米）
+    #[arg(long)]
+    pub food_radius: Option<u32>,
+
+    /// 美食类型代码，多个用 | 分隔
+    #[arg(long)]
+    pub food_types: Option<String>,
+
+    /// 最大返回结果数
+    #[arg(long)]
+    pub max_food_results: Option<u32>,
+
+    /// 通义千问模型名称
+    #[arg(long)]
+    pub qwen_model: Option<String>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// 加载并校验配置后退出，不启动服务
+    ValidateConfig,
+}
@@ -0,0 +1,1041 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{env, fmt, fs, path::{Path, PathBuf}, sync::{Arc, RwLock}};
+
+use crate::amap::Extensions;
+use crate::cli::Cli;
+use crate::error::AppError;
+use crate::geocoding::GEOCODER_NAMES;
+use crate::hours;
+use crate::i18n::{Lang, Msg};
+use crate::llm::LLM_PROVIDER_NAMES;
+use crate::poi_types;
+use crate::ranking::{SortBy, SORT_BY_NAMES};
+
+// 默认配置文件，命令行未指定 --config 时使用
+const DEFAULT_CONFIG_PATH: &str = "config.json";
+// 环境变量前缀，嵌套字段用双下划线分隔，例如 TOSS_SERVER__BIND
+const ENV_PREFIX: &str = "TOSS_";
+// 高德周边搜索允许的最大半径（米）和每页最大条数
+pub const AMAP_MAX_RADIUS: u32 = 50_000;
+pub const AMAP_MAX_PAGE_SIZE: u32 = 25;
+// 高德周边搜索最多翻到的页数
+pub const AMAP_MAX_PAGE: u32 = 100;
+// 单次请求最多合并的页数上限，max_merge_pages 不能超过它
+const MAX_MERGE_PAGES_LIMIT: u32 = 10;
+// 只能来自密钥文件或环境变量的字段
+const SECRET_FIELDS: [&str; 3] = ["api_key", "qwen_api_key", "admin_token"];
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Config {
+    pub username: String,
+    pub attempts: u32,
+    pub keywords: String,
+    pub city: String,
+    pub api_key: String,
+    pub output_file: String,
+    pub food_radius: u32,
+    pub food_types: String,
+    pub max_food_results: u32,
+    // 周边搜索的关键词，如 "兰州拉面"，与 food_types 同时生效；为空时只按类型搜索，请求可用 keyword 覆盖
+    #[serde(default)]
+    pub food_keywords: String,
+    // 周边搜索返回字段的详细程度："base" 或 "all"（包含评分、人均、营业时间、照片等），请求可用 extensions 覆盖
+    #[serde(default = "default_food_extensions")]
+    pub food_extensions: String,
+    // 判断营业状态、计算当前时间使用的时区，UTC 偏移，如 "+08:00"
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    // 单次请求通过 pages 参数最多合并的页数，每页 max_food_results 条
+    #[serde(default = "default_max_merge_pages")]
+    pub max_merge_pages: u32,
+    pub qwen_api_key: String,
+    pub qwen_model: String,
+    // 主模型的服务类型："dashscope"、"openai"（OpenAI 兼容接口）、"ollama"
+    #[serde(default = "default_llm_provider")]
+    pub llm_provider: String,
+    // 主模型的服务地址，为空时使用该服务类型的默认地址
+    #[serde(default)]
+    pub llm_base_url: String,
+    // 主模型单次调用的超时（秒），备用模型使用各自的 timeout_secs
+    #[serde(default = "default_llm_timeout")]
+    pub llm_timeout_secs: u64,
+    // 主模型 qwen_model 出现临时故障时按顺序尝试的备用模型
+    #[serde(default = "default_llm_fallbacks")]
+    pub llm_fallbacks: Vec<LlmModelConfig>,
+    // 主模型的上下文窗口（token）
+    #[serde(default = "default_context_tokens")]
+    pub llm_context_tokens: u32,
+    // 为模型回答预留的 token 数，提示词预算 = 回退链中最小的上下文窗口 - 该值
+    #[serde(default = "default_completion_tokens")]
+    pub llm_completion_tokens: u32,
+    // 地理编码服务，按顺序尝试："inputtips"（POI名称）、"geocode"（结构化地址）
+    #[serde(default = "default_geocoders")]
+    pub geocoders: Vec<String>,
+    // 提示词模板目录，目录中的模板覆盖或补充内置模板，修改后需要重启服务
+    #[serde(default = "default_prompt_dir")]
+    pub prompt_dir: String,
+    // 请求未指定 template 时使用的模板
+    #[serde(default = "default_template")]
+    pub default_template: String,
+    // 管理接口令牌，为空时禁用 /admin/* 接口
+    #[serde(default)]
+    pub admin_token: String,
+    // 旧版 config.json 没有 server 段，缺省时使用默认值
+    #[serde(default)]
+    pub server: ServerConfig,
+    // 上游调用失败时的退避参数，总尝试次数由 attempts 决定
+    #[serde(default)]
+    pub retry: RetryConfig,
+    // 搜索结果的排序方式和综合打分的权重
+    #[serde(default)]
+    pub ranking: RankingConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            username: "default_user".to_string(),
+            attempts: 3,
+            keywords: "大连理工大学开发区校区".to_string(),
+            city: "大连".to_string(),
+            api_key: String::new(),
+            output_file: "response_log.json".to_string(),
+            food_radius: 1000,
+            food_types: "050000".to_string(),
+            max_food_results: 5,
+            food_keywords: String::new(),
+            food_extensions: default_food_extensions(),
+            max_merge_pages: default_max_merge_pages(),
+            timezone: default_timezone(),
+            qwen_api_key: String::new(),
+            qwen_model: "qwen3-235b-a22b".to_string(),
+            llm_provider: default_llm_provider(),
+            llm_base_url: String::new(),
+            llm_timeout_secs: default_llm_timeout(),
+            llm_fallbacks: default_llm_fallbacks(),
+            llm_context_tokens: default_context_tokens(),
+            llm_completion_tokens: default_completion_tokens(),
+            geocoders: default_geocoders(),
+            prompt_dir: default_prompt_dir(),
+            default_template: default_template(),
+            admin_token: String::new(),
+            server: ServerConfig::default(),
+            retry: RetryConfig::default(),
+            ranking: RankingConfig::default(),
+        }
+    }
+}
+
+fn default_geocoders() -> Vec<String> {
+    vec!["inputtips".to_string()]
+}
+
+fn default_prompt_dir() -> String {
+    "prompts".to_string()
+}
+
+fn default_template() -> String {
+    "default".to_string()
+}
+
+// AI模型回退链中的一项
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LlmModelConfig {
+    #[serde(default = "default_llm_provider")]
+    pub provider: String,
+    pub model: String,
+    #[serde(default)]
+    pub base_url: String,          // 为空时使用该服务类型的默认地址
+    #[serde(default)]
+    pub api_key: String,           // dashscope 为空时使用 qwen_api_key
+    #[serde(default = "default_llm_timeout")]
+    pub timeout_secs: u64,
+    #[serde(default = "default_context_tokens")]
+    pub context_tokens: u32,       // 上下文窗口（token）
+}
+
+fn default_llm_fallbacks() -> Vec<LlmModelConfig> {
+    vec![LlmModelConfig {
+        provider: default_llm_provider(),
+        model: "qwen-turbo".to_string(),
+        base_url: String::new(),
+        api_key: String::new(),
+        timeout_secs: default_llm_timeout(),
+        context_tokens: default_context_tokens(),
+    }]
+}
+
+fn default_llm_provider() -> String {
+    "dashscope".to_string()
+}
+
+fn default_llm_timeout() -> u64 {
+    60
+}
+
+fn default_context_tokens() -> u32 {
+    8192
+}
+
+fn default_completion_tokens() -> u32 {
+    2048
+}
+
+fn default_food_extensions() -> String {
+    "base".to_string()
+}
+
+fn default_timezone() -> String {
+    "+08:00".to_string()
+}
+
+fn default_max_merge_pages() -> u32 {
+    3
+}
+
+// Web服务器配置
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub bind: Vec<String>,            // 监听地址，可配置多个
+    pub workers: Option<usize>,       // 工作线程数，None 表示按CPU核数
+    pub keep_alive_secs: u64,         // 0 表示关闭 keep-alive
+    pub json_limit: usize,            // JSON 请求体上限（字节）
+    pub payload_limit: usize,         // 原始请求体上限（字节）
+    pub shutdown_timeout_secs: u64,   // 优雅关闭等待时间
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind: vec!["127.0.0.1:8080".to_string()],
+            workers: None,
+            keep_alive_secs: 5,
+            json_limit: 32 * 1024,
+            payload_limit: 256 * 1024,
+            shutdown_timeout_secs: 30,
+        }
+    }
+}
+
+// 重试退避配置
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RetryConfig {
+    pub initial_backoff_ms: u64,   // 第一次重试前的等待时间
+    pub max_backoff_ms: u64,       // 单次等待上限，Retry-After 超过该值时不再重试
+    pub multiplier: f64,           // 每次重试等待时间的增长倍数
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            initial_backoff_ms: 200,
+            max_backoff_ms: 5000,
+            multiplier: 2.0,
+        }
+    }
+}
+
+// 排序配置，见 ranking::rank
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RankingConfig {
+    pub default_sort_by: String,   // 请求未指定 sort_by 时使用，见 ranking::SORT_BY_NAMES
+    pub distance_weight: f64,      // 综合分中距离的权重
+    pub rating_weight: f64,        // 综合分中评分的权重
+    pub cost_weight: f64,          // 综合分中人均消费的权重（越便宜分数越高）
+    pub diversity_penalty: f64,    // diversity 排序中，每出现一家同类餐厅后续同类降低的分数
+}
+
+impl Default for RankingConfig {
+    fn default() -> Self {
+        RankingConfig {
+            default_sort_by: "distance".to_string(),
+            distance_weight: 0.5,
+            rating_weight: 0.3,
+            cost_weight: 0.2,
+            diversity_penalty: 0.2,
+        }
+    }
+}
+
+impl ServerConfig {
+    // 用环境变量覆盖服务器配置，ACTIX_WEB_BIND 支持逗号分隔多个地址
+    fn apply_env(&mut self) -> Result<(), AppError> {
+        if let Ok(bind) = env::var("ACTIX_WEB_BIND") {
+            let addrs: Vec<String> = bind
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+            if !addrs.is_empty() {
+                self.bind = addrs;
+            }
+        }
+        if let Ok(workers) = env::var("ACTIX_WEB_WORKERS") {
+            self.workers = Some(parse_env("ACTIX_WEB_WORKERS", &workers)?);
+        }
+        if let Ok(keep_alive) = env::var("ACTIX_WEB_KEEP_ALIVE") {
+            self.keep_alive_secs = parse_env("ACTIX_WEB_KEEP_ALIVE", &keep_alive)?;
+        }
+        if let Ok(limit) = env::var("ACTIX_WEB_JSON_LIMIT") {
+            self.json_limit = parse_env("ACTIX_WEB_JSON_LIMIT", &limit)?;
+        }
+        if let Ok(limit) = env::var("ACTIX_WEB_PAYLOAD_LIMIT") {
+            self.payload_limit = parse_env("ACTIX_WEB_PAYLOAD_LIMIT", &limit)?;
+        }
+        if let Ok(timeout) = env::var("ACTIX_WEB_SHUTDOWN_TIMEOUT") {
+            self.shutdown_timeout_secs = parse_env("ACTIX_WEB_SHUTDOWN_TIMEOUT", &timeout)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_env<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, AppError> {
+    value
+        .trim()
+        .parse::<T>()
+        .map_err(|_| AppError::Config(format!("环境变量 {} 的值无效: {}", name, value)))
+}
+
+impl Config {
+    // 返回密钥脱敏后的副本，用于打印和日志
+    pub fn redacted(&self) -> Config {
+        let mut config = self.clone();
+        config.api_key = redact(&config.api_key);
+        config.qwen_api_key = redact(&config.qwen_api_key);
+        config.admin_token = redact(&config.admin_token);
+        for fallback in &mut config.llm_fallbacks {
+            fallback.api_key = redact(&fallback.api_key);
+        }
+        config
+    }
+
+    // 完整的模型回退链：主模型在前
+    // 通义千问的备用模型未配置密钥时沿用 qwen_api_key，其他服务不会收到该密钥
+    pub fn llm_chain(&self) -> Vec<LlmModelConfig> {
+        let primary = LlmModelConfig {
+            provider: self.llm_provider.clone(),
+            model: self.qwen_model.clone(),
+            base_url: self.llm_base_url.clone(),
+            api_key: self.qwen_api_key.clone(),
+            timeout_secs: self.llm_timeout_secs,
+            context_tokens: self.llm_context_tokens,
+        };
+        std::iter::once(primary)
+            .chain(self.llm_fallbacks.iter().map(|fallback| {
+                let inherit_key = fallback.api_key.is_empty() && fallback.provider == "dashscope";
+                LlmModelConfig {
+                    api_key: if inherit_key { self.qwen_api_key.clone() } else { fallback.api_key.clone() },
+                    ..fallback.clone()
+                }
+            }))
+            .collect()
+    }
+
+    // 提示词可用的 token 数：同一份提示词可能发给回退链中的任意模型，按最小的上下文窗口计算
+    pub fn prompt_token_budget(&self) -> usize {
+        let context = self.llm_chain().iter().map(|entry| entry.context_tokens).min().unwrap_or(self.llm_context_tokens);
+        context.saturating_sub(self.llm_completion_tokens) as usize
+    }
+
+    // 命令行参数覆盖
+    fn apply_cli(&mut self, cli: &Cli) {
+        if !cli.bind.is_empty() {
+            self.server.bind = cli.bind.clone();
+        }
+        if let Some(workers) = cli.workers {
+            self.server.workers = Some(workers);
+        }
+        if let Some(city) = &cli.city {
+            self.city = city.clone();
+        }
+        if let Some(radius) = cli.food_radius {
+            self.food_radius = radius;
+        }
+        if let Some(types) = &cli.food_types {
+            self.food_types = types.clone();
+        }
+        if let Some(max) = cli.max_food_results {
+            self.max_food_results = max;
+        }
+        if let Some(model) = &cli.qwen_model {
+            self.qwen_model = model.clone();
+        }
+    }
+}
+
+fn redact(secret: &str) -> String {
+    if secret.is_empty() {
+        String::new()
+    } else {
+        "******".to_string()
+    }
+}
+
+// 实际使用的配置文件路径：命令行指定的路径必须存在，默认路径不存在时跳过
+pub fn config_path(cli: &Cli) -> Option<PathBuf> {
+    match &cli.config {
+        Some(path) => Some(path.clone()),
+        None => {
+            let path = PathBuf::from(DEFAULT_CONFIG_PATH);
+            path.exists().then_some(path)
+        }
+    }
+}
+
+// 按扩展名解析 JSON / TOML / YAML 文件
+fn read_layer(path: &Path) -> Result<Value, AppError> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| AppError::Config(format!("读取文件 {} 失败: {}", path.display(), e)))?;
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("json").to_lowercase();
+    let parse_error = |e: &dyn fmt::Display| AppError::Config(format!("解析 {} 失败: {}", path.display(), e));
+    let value: Value = match ext.as_str() {
+        "toml" => toml::from_str(&contents).map_err(|e| parse_error(&e))?,
+        "yaml" | "yml" => serde_yaml::from_str(&contents).map_err(|e| parse_error(&e))?,
+        _ => serde_json::from_str(&contents).map_err(|e| parse_error(&e))?,
+    };
+    if !value.is_object() {
+        return Err(AppError::Config(format!("{} 的顶层必须是对象", path.display())));
+    }
+    Ok(value)
+}
+
+// 深度合并：overlay 中的字段覆盖 base，对象递归合并
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                merge(base.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+// 应用 TOSS_* 环境变量（vars 通常为 env::vars()），按目标字段当前值的类型解析
+fn apply_env_layer(merged: &mut Value, vars: impl IntoIterator<Item = (String, String)>) -> Result<(), AppError> {
+    let mut vars: Vec<(String, String)> = vars
+        .into_iter()
+        .filter(|(k, _)| k.starts_with(ENV_PREFIX))
+        .collect();
+    vars.sort();
+
+    for (name, raw) in vars {
+        let path: Vec<String> = name[ENV_PREFIX.len()..]
+            .to_lowercase()
+            .split("__")
+            .map(|s| s.to_string())
+            .collect();
+
+        // 不对应任何配置字段的变量（如 TOSS_CONFIG）由命令行解析处理
+        let Some(slot) = field_mut(merged, &path) else {
+            continue;
+        };
+        *slot = env_value(&name, &raw, slot)?;
+    }
+    Ok(())
+}
+
+// 数字表示数组下标，例如 TOSS_LLM_FALLBACKS__0__API_KEY
+fn field_mut<'a>(root: &'a mut Value, path: &[String]) -> Option<&'a mut Value> {
+    path.iter().try_fold(root, |slot, key| match (slot, key.parse::<usize>()) {
+        (Value::Array(items), Ok(index)) => items.get_mut(index),
+        (slot, _) => slot.get_mut(key.as_str()),
+    })
+}
+
+fn env_value(name: &str, raw: &str, current: &Value) -> Result<Value, AppError> {
+    let raw = raw.trim();
+    Ok(match current {
+        Value::Bool(_) => Value::Bool(parse_env::<bool>(name, raw)?),
+        Value::Number(n) if n.is_f64() => json_number(parse_env::<f64>(name, raw)?, name)?,
+        Value::Number(_) | Value::Null => Value::from(parse_env::<u64>(name, raw)?),
+        Value::Array(_) => Value::Array(
+            raw.split(',')
+                .map(|s| s.trim())
+                .filter(|s| !s.is_empty())
+                .map(|s| Value::String(s.to_string()))
+                .collect(),
+        ),
+        _ => Value::String(raw.to_string()),
+    })
+}
+
+fn json_number(n: f64, name: &str) -> Result<Value, AppError> {
+    serde_json::Number::from_f64(n)
+        .map(Value::Number)
+        .ok_or_else(|| AppError::Config(format!("环境变量 {} 的值无效: {}", name, n)))
+}
+
+fn config_error(e: serde_json::Error) -> AppError {
+    AppError::Config(format!("配置格式错误: {}", e))
+}
+
+// 配置文件通常会提交到仓库或打进镜像，其中出现密钥字段（包括 llm_fallbacks[].api_key）时拒绝加载
+fn reject_secrets(path: &Path, layer: &Value) -> Result<(), AppError> {
+    let mut found: Vec<Vec<String>> = Vec::new();
+    find_secrets(layer, &mut Vec::new(), &mut found);
+    if found.is_empty() {
+        return Ok(());
+    }
+    let fields: Vec<String> = found.iter().map(|path| display_path(path)).collect();
+    let env_names: Vec<String> = found
+        .iter()
+        .map(|path| format!("{}{}", ENV_PREFIX, path.join("__").to_uppercase()))
+        .collect();
+    Err(AppError::Config(format!(
+        "配置文件 {} 中不允许出现密钥字段 {:?}，请改用 --secrets-file 或环境变量 {}",
+        path.display(),
+        fields,
+        env_names.join(" / ")
+    )))
+}
+
+// 递归查找密钥字段，路径中的数组下标记为数字，如 ["llm_fallbacks", "0", "api_key"]
+fn find_secrets(value: &Value, path: &mut Vec<String>, found: &mut Vec<Vec<String>>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                path.push(key.clone());
+                if SECRET_FIELDS.contains(&key.as_str()) {
+                    found.push(path.clone());
+                } else {
+                    find_secrets(value, path, found);
+                }
+                path.pop();
+            }
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                path.push(i.to_string());
+                find_secrets(item, path, found);
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+// ["llm_fallbacks", "0", "api_key"] -> "llm_fallbacks[0].api_key"
+fn display_path(path: &[String]) -> String {
+    path.iter().fold(String::new(), |mut out, key| {
+        if key.parse::<usize>().is_ok() {
+            out.push_str(&format!("[{}]", key));
+        } else {
+            if !out.is_empty() {
+                out.push('.');
+            }
+            out.push_str(key);
+        }
+        out
+    })
+}
+
+// 合并密钥文件：顶层只允许 SECRET_FIELDS，另外可以按下标为备用模型提供密钥：
+// {"llm_fallbacks": [{"api_key": "..."}, {}]}，空对象表示跳过该项
+fn merge_secrets(merged: &mut Value, secrets: Value, path: &Path) -> Result<(), AppError> {
+    let not_allowed = |field: &str| AppError::Config(format!("密钥文件 {} 中不允许出现字段: {}", path.display(), field));
+    for (key, value) in secrets.as_object().cloned().into_iter().flatten() {
+        if SECRET_FIELDS.contains(&key.as_str()) {
+            merged[key.as_str()] = value;
+            continue;
+        }
+        if key != "llm_fallbacks" {
+            return Err(not_allowed(&key));
+        }
+        let Value::Array(entries) = value else {
+            return Err(not_allowed(&key));
+        };
+        for (i, entry) in entries.into_iter().enumerate() {
+            let field = format!("llm_fallbacks[{}]", i);
+            let Value::Object(entry) = entry else {
+                return Err(not_allowed(&field));
+            };
+            for (name, secret) in entry {
+                if name != "api_key" {
+                    return Err(not_allowed(&format!("{}.{}", field, name)));
+                }
+                let Some(target) = merged["llm_fallbacks"].get_mut(i).and_then(Value::as_object_mut) else {
+                    return Err(AppError::Config(format!(
+                        "密钥文件 {} 中的 {} 没有对应的备用模型",
+                        path.display(),
+                        field
+                    )));
+                };
+                target.insert(name, secret);
+            }
+        }
+    }
+    Ok(())
+}
+
+// 分层加载配置：默认值 → 配置文件 → 密钥文件 → ACTIX_WEB_* / TOSS_* 环境变量 → 命令行
+// 配置文件缺失时只使用默认值，不会再把默认配置（以及密钥）写回磁盘
+pub fn load_config(cli: &Cli) -> Result<Config, AppError> {
+    load_layers(cli, env::vars())
+}
+
+fn load_layers(cli: &Cli, vars: impl IntoIterator<Item = (String, String)>) -> Result<Config, AppError> {
+    let mut merged = serde_json::to_value(Config::default()).map_err(config_error)?;
+
+    if let Some(path) = config_path(cli) {
+        let layer = read_layer(&path)?;
+        reject_secrets(&path, &layer)?;
+        merge(&mut merged, layer);
+    }
+
+    if let Some(path) = &cli.secrets_file {
+        let secrets = read_layer(path)?;
+        merge_secrets(&mut merged, secrets, path)?;
+    }
+
+    let mut config: Config = serde_json::from_value(merged).map_err(config_error)?;
+    config.server.apply_env()?;
+
+    let mut merged = serde_json::to_value(&config).map_err(config_error)?;
+    apply_env_layer(&mut merged, vars)?;
+    let mut config: Config = serde_json::from_value(merged).map_err(config_error)?;
+
+    config.apply_cli(cli);
+    Ok(config)
+}
+
+// 单个字段的校验错误
+#[derive(Serialize, Debug, Clone)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+    // 来自消息目录的错误，返回给客户端时按请求语言重新生成 message
+    #[serde(skip)]
+    pub msg: Option<Msg>,
+}
+
+// 汇总所有字段错误，一次性报告
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    pub errors: Vec<FieldError>,
+}
+
+impl ValidationReport {
+    pub fn push_msg(&mut self, field: &str, msg: Msg) {
+        self.errors.push(FieldError {
+            field: field.to_string(),
+            message: msg.text(Lang::ZhCn),
+            msg: Some(msg),
+        });
+    }
+
+    // 按语言生成的字段错误列表
+    pub fn localized(&self, lang: Lang) -> Vec<FieldError> {
+        self.errors
+            .iter()
+            .map(|error| FieldError {
+                field: error.field.clone(),
+                message: error.msg.as_ref().map(|msg| msg.text(lang)).unwrap_or_else(|| error.message.clone()),
+                msg: None,
+            })
+            .collect()
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "校验失败，共 {} 项错误:", self.errors.len())?;
+        for error in &self.errors {
+            writeln!(f, "  - {}: {}", error.field, error.message)?;
+        }
+        Ok(())
+    }
+}
+
+
+// 运行时配置，支持热重载时原子替换
+// 读取方拿到的是 Arc 快照，正在处理的请求不受替换影响
+pub struct ConfigStore {
+    current: RwLock<Arc<Config>>,
+    cli: Cli,
+    templates: Vec<String>,   // 启动时加载的提示词模板名，模板不随配置重新加载
+}
+
+impl ConfigStore {
+    pub fn new(config: Config, cli: Cli, templates: Vec<String>) -> Self {
+        ConfigStore {
+            current: RwLock::new(Arc::new(config)),
+            cli,
+            templates,
+        }
+    }
+
+    pub fn current(&self) -> Arc<Config> {
+        self.current.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    // 需要监听变更的文件：配置文件和密钥文件
+    pub fn watched_files(&self) -> Vec<PathBuf> {
+        config_path(&self.cli)
+            .into_iter()
+            .chain(self.cli.secrets_file.clone())
+            .collect()
+    }
+
+    // 按启动时相同的分层规则重新加载，校验通过才替换；失败时保留旧配置
+    pub fn reload(&self) -> Result<Arc<Config>, AppError> {
+        let config = load_config(&self.cli)?;
+        let mut report = config.validate();
+        let template = config.default_template.trim();
+        if !template.is_empty() && !self.templates.iter().any(|name| name == template) {
+            report.push_msg(
+                "default_template",
+                Msg::new("validation.unknown_template").arg("name", template).arg("available", format!("{:?}", self.templates)),
+            );
+        }
+        // 配置文件有问题不是请求的错，返回 CONFIG_ERROR 而不是 VALIDATION_FAILED
+        if !report.is_ok() {
+            return Err(AppError::InvalidConfig(report));
+        }
+
+        let config = Arc::new(config);
+        let mut current = self.current.write().unwrap_or_else(|e| e.into_inner());
+        if serde_json::to_value(&current.server).ok() != serde_json::to_value(&config.server).ok() {
+            println!("⚠️  server 段的修改需要重启服务才能生效");
+        }
+        *current = config.clone();
+        Ok(config)
+    }
+}
+
+impl Config {
+    // 校验所有字段，返回全部错误而不是遇到第一个就停止；文本来自消息目录，管理接口按请求语言返回
+    pub fn validate(&self) -> ValidationReport {
+        let mut report = ValidationReport::default();
+
+        for (field, value) in [
+            ("username", &self.username),
+            ("keywords", &self.keywords),
+            ("city", &self.city),
+            ("api_key", &self.api_key),
+            ("qwen_model", &self.qwen_model),
+            ("default_template", &self.default_template),
+        ] {
+            if value.trim().is_empty() {
+                report.push_msg(field, Msg::new("validation.required"));
+            }
+        }
+        // 本地的 OpenAI 兼容服务和 Ollama 通常不需要密钥
+        if self.llm_provider == "dashscope" && self.qwen_api_key.trim().is_empty() {
+            report.push_msg("qwen_api_key", Msg::new("validation.required"));
+        }
+
+        check_range(&mut report, "attempts", self.attempts, 1, 10);
+        if !(1..=AMAP_MAX_RADIUS).contains(&self.food_radius) {
+            report.push_msg(
+                "food_radius",
+                Msg::new("validation.radius_range").arg("max", AMAP_MAX_RADIUS).arg("value", self.food_radius),
+            );
+        }
+        if !(1..=AMAP_MAX_PAGE_SIZE).contains(&self.max_food_results) {
+            report.push_msg(
+                "max_food_results",
+                Msg::new("config.page_size_range").arg("max", AMAP_MAX_PAGE_SIZE).arg("value", self.max_food_results),
+            );
+        }
+        if Extensions::parse(&self.food_extensions).is_none() {
+            report.push_msg("food_extensions", Msg::new("validation.extensions").arg("value", &self.food_extensions));
+        }
+        check_range(&mut report, "max_merge_pages", self.max_merge_pages, 1, MAX_MERGE_PAGES_LIMIT);
+        if hours::parse_offset(&self.timezone).is_none() {
+            report.push_msg("timezone", Msg::new("config.timezone").arg("value", &self.timezone));
+        }
+        // 设置了 food_keywords 时 food_types 可以为空，只按关键词搜索
+        if !self.food_types.trim().is_empty() || self.food_keywords.trim().is_empty() {
+            if let Err(msg) = check_type_codes(&self.food_types) {
+                report.push_msg("food_types", msg);
+            }
+        }
+
+        if self.geocoders.is_empty() {
+            report.push_msg("geocoders", Msg::new("config.geocoders_empty"));
+        }
+        for (i, name) in self.geocoders.iter().enumerate() {
+            if !GEOCODER_NAMES.contains(&name.as_str()) {
+                report.push_msg(
+                    &format!("geocoders[{}]", i),
+                    Msg::new("config.unknown_geocoder").arg("value", name).arg("available", format!("{:?}", GEOCODER_NAMES)),
+                );
+            }
+        }
+
+        check_llm_provider(&mut report, "llm_provider", "llm_base_url", &self.llm_provider, &self.llm_base_url);
+        check_range(&mut report, "llm_timeout_secs", self.llm_timeout_secs, 1, 600);
+        for (i, fallback) in self.llm_fallbacks.iter().enumerate() {
+            if fallback.model.trim().is_empty() {
+                report.push_msg(&format!("llm_fallbacks[{}].model", i), Msg::new("validation.required"));
+            }
+            check_llm_provider(
+                &mut report,
+                &format!("llm_fallbacks[{}].provider", i),
+                &format!("llm_fallbacks[{}].base_url", i),
+                &fallback.provider,
+                &fallback.base_url,
+            );
+            check_range(&mut report, &format!("llm_fallbacks[{}].timeout_secs", i), fallback.timeout_secs, 1, 600);
+            check_context_tokens(&mut report, &format!("llm_fallbacks[{}].context_tokens", i), fallback.context_tokens, self.llm_completion_tokens);
+        }
+        check_context_tokens(&mut report, "llm_context_tokens", self.llm_context_tokens, self.llm_completion_tokens);
+        if self.llm_completion_tokens == 0 {
+            report.push_msg("llm_completion_tokens", Msg::new("config.positive"));
+        }
+
+        self.server.validate(&mut report);
+        self.retry.validate(&mut report);
+        self.ranking.validate(&mut report);
+        report
+    }
+}
+
+impl ServerConfig {
+    fn validate(&self, report: &mut ValidationReport) {
+        if self.bind.is_empty() {
+            report.push_msg("server.bind", Msg::new("config.bind_empty"));
+        }
+        for (i, addr) in self.bind.iter().enumerate() {
+            let valid = addr
+                .rsplit_once(':')
+                .map(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok())
+                .unwrap_or(false);
+            if !valid {
+                report.push_msg(&format!("server.bind[{}]", i), Msg::new("config.bind_addr").arg("value", addr));
+            }
+        }
+        if self.workers == Some(0) {
+            report.push_msg("server.workers", Msg::new("config.positive"));
+        }
+        if self.json_limit == 0 {
+            report.push_msg("server.json_limit", Msg::new("config.positive"));
+        }
+        if self.payload_limit == 0 {
+            report.push_msg("server.payload_limit", Msg::new("config.positive"));
+        }
+    }
+}
+
+impl RetryConfig {
+    fn validate(&self, report: &mut ValidationReport) {
+        if self.initial_backoff_ms == 0 {
+            report.push_msg("retry.initial_backoff_ms", Msg::new("config.positive"));
+        }
+        if self.max_backoff_ms < self.initial_backoff_ms {
+            report.push_msg(
+                "retry.max_backoff_ms",
+                Msg::new("config.max_backoff").arg("min", self.initial_backoff_ms).arg("value", self.max_backoff_ms),
+            );
+        }
+        check_range(report, "retry.multiplier", self.multiplier, 1.0, 10.0);
+    }
+}
+
+impl RankingConfig {
+    fn validate(&self, report: &mut ValidationReport) {
+        if SortBy::parse(&self.default_sort_by).is_none() {
+            report.push_msg(
+                "ranking.default_sort_by",
+                Msg::new("validation.unknown_sort_by")
+                    .arg("value", &self.default_sort_by)
+                    .arg("available", format!("{:?}", SORT_BY_NAMES)),
+            );
+        }
+        let weights = [
+            ("ranking.distance_weight", self.distance_weight),
+            ("ranking.rating_weight", self.rating_weight),
+            ("ranking.cost_weight", self.cost_weight),
+        ];
+        for (field, weight) in weights {
+            check_range(report, field, weight, 0.0, 1.0);
+        }
+        if weights.iter().all(|(_, weight)| *weight == 0.0) {
+            report.push_msg("ranking", Msg::new("config.weights_zero"));
+        }
+        check_range(report, "ranking.diversity_penalty", self.diversity_penalty, 0.0, 1.0);
+    }
+}
+
+fn check_range<T: PartialOrd + Copy + fmt::Display>(report: &mut ValidationReport, field: &str, value: T, min: T, max: T) {
+    if !(min..=max).contains(&value) {
+        report.push_msg(field, Msg::new("config.range").arg("min", min).arg("max", max).arg("value", value));
+    }
+}
+
+fn check_llm_provider(report: &mut ValidationReport, provider_field: &str, url_field: &str, provider: &str, base_url: &str) {
+    if !LLM_PROVIDER_NAMES.contains(&provider) {
+        report.push_msg(
+            provider_field,
+            Msg::new("config.unknown_llm_provider").arg("value", provider).arg("available", format!("{:?}", LLM_PROVIDER_NAMES)),
+        );
+    }
+    if !base_url.is_empty() && !base_url.starts_with("http://") && !base_url.starts_with("https://") {
+        report.push_msg(url_field, Msg::new("config.base_url").arg("value", base_url));
+    }
+}
+
+// 上下文窗口扣除预留的回答 token 后，至少要能放下不含餐厅列表的提示词
+const MIN_PROMPT_TOKENS: u32 = 1024;
+
+fn check_context_tokens(report: &mut ValidationReport, field: &str, context_tokens: u32, completion_tokens: u32) {
+    if context_tokens < completion_tokens.saturating_add(MIN_PROMPT_TOKENS) {
+        report.push_msg(
+            field,
+            Msg::new("config.context_tokens").arg("min", MIN_PROMPT_TOKENS).arg("value", context_tokens),
+        );
+    }
+}
+
+// 高德类型代码或分类名称，多个用 | 连接；返回转换后的类型代码，见 poi_types::resolve_type_codes
+pub fn check_type_codes(types: &str) -> Result<String, Msg> {
+    if types.trim().is_empty() {
+        return Err(Msg::new("validation.required"));
+    }
+    poi_types::resolve_type_codes(types)
+        .map_err(|invalid| Msg::new("validation.type_codes").arg("value", invalid.join("|")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    // 写入临时目录，文件名带进程号避免并行测试冲突
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("toss-config-{}-{}", process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    fn config_error_text(result: Result<Config, AppError>) -> String {
+        match result {
+            Err(AppError::Config(message)) => message,
+            other => panic!("unexpected result: {:?}", other.map(|c| c.redacted())),
+        }
+    }
+
+    #[test]
+    fn layers_file_secrets_env_and_cli_in_order() {
+        let config_file = temp_file(
+            "layers.toml",
+            r#"
+city = "大连"
+food_radius = 1000
+food_types = "050100"
+max_food_results = 10
+qwen_model = "qwen-plus"
+
+[[llm_fallbacks]]
+provider = "openai"
+model = "gpt-4o-mini"
+"#,
+        );
+        let secrets_file = temp_file(
+            "layers-secrets.json",
+            r#"{"qwen_api_key": "sk-file", "admin_token": "token-file", "llm_fallbacks": [{"api_key": "sk-fallback"}]}"#,
+        );
+        let cli = Cli {
+            config: Some(config_file.clone()),
+            secrets_file: Some(secrets_file.clone()),
+            city: Some("北京".to_string()),
+            max_food_results: Some(5),
+            ..Cli::default()
+        };
+        let env = vars(&[
+            ("TOSS_QWEN_API_KEY", "sk-env"),
+            ("TOSS_FOOD_RADIUS", "1500"),
+            ("TOSS_MAX_FOOD_RESULTS", "20"),
+            ("TOSS_LLM_FALLBACKS__0__MODEL", "gpt-4o"),
+            ("TOSS_CONFIG", "ignored.json"),
+            ("OTHER_FOOD_RADIUS", "9"),
+        ]);
+        let config = load_layers(&cli, env).unwrap();
+        fs::remove_file(config_file).ok();
+        fs::remove_file(secrets_file).ok();
+
+        assert_eq!(config.food_types, "050100");           // 配置文件
+        assert_eq!(config.qwen_model, "qwen-plus");
+        assert_eq!(config.admin_token, "token-file");      // 密钥文件
+        assert_eq!(config.llm_fallbacks[0].api_key, "sk-fallback");
+        assert_eq!(config.llm_fallbacks[0].provider, "openai");
+        assert_eq!(config.qwen_api_key, "sk-env");         // 环境变量覆盖密钥文件
+        assert_eq!(config.food_radius, 1500);              // 环境变量覆盖配置文件
+        assert_eq!(config.llm_fallbacks[0].model, "gpt-4o");
+        assert_eq!(config.city, "北京");                   // 命令行覆盖配置文件
+        assert_eq!(config.max_food_results, 5);            // 命令行覆盖环境变量
+        assert_eq!(config.username, Config::default().username);   // 未设置的字段使用默认值
+    }
+
+    #[test]
+    fn rejects_invalid_env_values() {
+        let config_file = temp_file("env.json", "{}");
+        let cli = Cli { config: Some(config_file.clone()), ..Cli::default() };
+        let message = config_error_text(load_layers(&cli, vars(&[("TOSS_FOOD_RADIUS", "一千米")])));
+        fs::remove_file(config_file).ok();
+        assert!(message.contains("TOSS_FOOD_RADIUS"), "{}", message);
+    }
+
+    #[test]
+    fn rejects_secrets_in_config_file() {
+        let layer = serde_json::json!({
+            "city": "大连",
+            "admin_token": "t",
+            "llm_fallbacks": [{"model": "qwen-turbo"}, {"model": "gpt-4o", "api_key": "sk"}]
+        });
+        let message = match reject_secrets(Path::new("config.json"), &layer) {
+            Err(AppError::Config(message)) => message,
+            other => panic!("unexpected result: {:?}", other),
+        };
+        assert!(message.contains(r#"["admin_token", "llm_fallbacks[1].api_key"]"#), "{}", message);
+        assert!(message.contains("TOSS_ADMIN_TOKEN / TOSS_LLM_FALLBACKS__1__API_KEY"), "{}", message);
+
+        assert!(reject_secrets(Path::new("config.json"), &serde_json::json!({"city": "大连"})).is_ok());
+    }
+
+    #[test]
+    fn secrets_file_only_accepts_secret_fields() {
+        let mut merged = serde_json::to_value(Config::default()).unwrap();
+        let path = Path::new("secrets.json");
+        let result = merge_secrets(&mut merged, serde_json::json!({"city": "大连"}), path);
+        assert!(matches!(result, Err(AppError::Config(message)) if message.ends_with("city")));
+
+        let result = merge_secrets(&mut merged, serde_json::json!({"llm_fallbacks": [{"model": "x"}]}), path);
+        assert!(matches!(result, Err(AppError::Config(message)) if message.ends_with("llm_fallbacks[0].model")));
+
+        // 默认只有一个备用模型
+        let result = merge_secrets(&mut merged, serde_json::json!({"llm_fallbacks": [{}, {"api_key": "sk"}]}), path);
+        assert!(matches!(result, Err(AppError::Config(message)) if message.contains("没有对应的备用模型")));
+    }
+
+    #[test]
+    fn print_config_redacts_secrets() {
+        let mut config = Config {
+            api_key: "amap-secret".to_string(),
+            qwen_api_key: "sk-qwen-secret".to_string(),
+            admin_token: "admin-secret".to_string(),
+            ..Config::default()
+        };
+        config.llm_fallbacks[0].api_key = "sk-fallback-secret".to_string();
+        let text = serde_json::to_string_pretty(&config.redacted()).unwrap();
+        assert!(!text.contains("secret"), "{}", text);
+        assert_eq!(text.matches("\"******\"").count(), 4);
+
+        // 未设置的密钥保持为空，便于看出缺少哪些密钥
+        let redacted = Config::default().redacted();
+        assert!(redacted.qwen_api_key.is_empty() && redacted.admin_token.is_empty());
+    }
+}
@@ -0,0 +1,142 @@
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use serde_json::{json, Value};
+use std::fmt;
+
+use crate::amap::AmapError;
+use crate::config::ValidationReport;
+use crate::geocoding::Candidate;
+use crate::i18n::{self, Lang, Msg};
+use crate::retry::AttemptRecord;
+use crate::ApiResponse;
+
+// 统一错误类型，处理函数直接用 ? 返回，由 ResponseError 生成统一的JSON错误响应
+// 返回给客户端的文本来自消息目录，由 i18n::localize_errors 按请求语言生成
+#[derive(Debug)]
+pub enum AppError {
+    Geocoding(AmapError),                // 地理编码服务调用失败
+    LocationNotFound(String),            // 地理编码没有结果
+    AmbiguousLocation(Vec<Candidate>),   // 多个候选地点，需要客户端选择
+    PoiSearch(AmapError),                // 周边美食搜索失败
+    PoiDetail(AmapError),                // POI 详情查询失败
+    PoiNotFound(String),                 // POI 详情接口没有该 id
+    Llm(String),                         // AI 调用失败
+    Config(String),                      // 配置加载失败
+    InvalidConfig(ValidationReport),     // 重新加载的配置校验失败，继续使用旧配置
+    Template(String),                    // 提示词模板加载或渲染失败
+    Validation(ValidationReport),        // 请求参数或配置校验失败
+    Unauthorized,                        // 管理接口令牌缺失或不正确
+}
+
+impl AppError {
+    // 稳定的机器可读错误码，客户端应根据它而不是 message 判断错误类型
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Geocoding(_) => "GEOCODING_FAILED",
+            AppError::LocationNotFound(_) => "LOCATION_NOT_FOUND",
+            AppError::AmbiguousLocation(_) => "AMBIGUOUS_LOCATION",
+            AppError::PoiSearch(_) => "POI_SEARCH_FAILED",
+            AppError::PoiDetail(_) => "POI_DETAIL_FAILED",
+            AppError::PoiNotFound(_) => "POI_NOT_FOUND",
+            AppError::Llm(_) => "LLM_FAILED",
+            AppError::Config(_) | AppError::InvalidConfig(_) => "CONFIG_ERROR",
+            AppError::Template(_) => "TEMPLATE_ERROR",
+            AppError::Validation(_) => "VALIDATION_FAILED",
+            AppError::Unauthorized => "UNAUTHORIZED",
+        }
+    }
+
+    // 错误类型的简短说明，消息目录中的键为 error.<code>
+    fn message(&self, lang: Lang) -> String {
+        i18n::text(lang, &format!("error.{}", self.code())).to_string()
+    }
+
+    // 具体的错误原因；上游返回的原始信息（高德 info、模型错误等）保持原文
+    pub fn detail(&self, lang: Lang) -> String {
+        match self {
+            AppError::Geocoding(e) | AppError::PoiSearch(e) | AppError::PoiDetail(e) => e.localized(lang),
+            AppError::PoiNotFound(id) => Msg::new("detail.poi_not_found").arg("id", id).text(lang),
+            AppError::LocationNotFound(query) => Msg::new("detail.location_not_found").arg("query", query).text(lang),
+            AppError::AmbiguousLocation(candidates) => {
+                Msg::new("detail.ambiguous_location").arg("count", candidates.len()).text(lang)
+            }
+            AppError::Llm(e) => Msg::new("detail.llm").arg("detail", e).text(lang),
+            AppError::Config(e) | AppError::Template(e) => e.clone(),
+            AppError::Unauthorized => i18n::text(lang, "detail.unauthorized").to_string(),
+            AppError::Validation(report) | AppError::InvalidConfig(report) => {
+                let mut text = Msg::new("detail.validation").arg("count", report.errors.len()).text(lang);
+                for error in report.localized(lang) {
+                    text.push_str(&format!("\n  - {}: {}", error.field, error.message));
+                }
+                text
+            }
+        }
+    }
+
+    // 附加的结构化数据：候选地点列表、字段错误列表
+    fn data(&self, lang: Lang) -> Option<Value> {
+        match self {
+            AppError::AmbiguousLocation(candidates) => Some(json!({ "candidates": candidates })),
+            AppError::Validation(report) | AppError::InvalidConfig(report) => Some(json!({ "errors": report.localized(lang) })),
+            _ => None,
+        }
+    }
+
+    // attempts 为请求中各上游调用的尝试次数，所有重试都失败时最需要这些信息
+    pub fn to_response(&self, lang: Lang, attempts: &[AttemptRecord]) -> HttpResponse {
+        let mut data = self.data(lang);
+        if !attempts.is_empty() {
+            data.get_or_insert_with(|| json!({}))["attempts"] = json!(attempts);
+        }
+        HttpResponse::build(self.status_code()).json(ApiResponse {
+            success: false,
+            code: Some(self.code().to_string()),
+            message: self.message(lang),
+            data,
+            error: Some(self.detail(lang)),
+        })
+    }
+
+    // 单个字段的校验错误，文本来自消息目录
+    pub fn invalid_msg(field: &str, msg: Msg) -> Self {
+        let mut report = ValidationReport::default();
+        report.push_msg(field, msg);
+        AppError::Validation(report)
+    }
+}
+
+// 日志和控制台输出使用简体中文
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Validation(report) | AppError::InvalidConfig(report) => write!(f, "{}", report),
+            _ => write!(f, "{}", self.detail(Lang::ZhCn)),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::Geocoding(e) | AppError::PoiSearch(e) | AppError::PoiDetail(e) => e.status_code(),
+            AppError::LocationNotFound(_) | AppError::PoiNotFound(_) => StatusCode::NOT_FOUND,
+            AppError::AmbiguousLocation(_) => StatusCode::MULTIPLE_CHOICES,
+            AppError::Llm(_) => StatusCode::BAD_GATEWAY,
+            AppError::Config(_) | AppError::InvalidConfig(_) | AppError::Template(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Validation(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized => StatusCode::UNAUTHORIZED,
+        }
+    }
+
+    // 没有经过 i18n::localize_errors 时使用默认语言
+    fn error_response(&self) -> HttpResponse {
+        self.to_response(Lang::default(), &[])
+    }
+}
+
+impl From<ValidationReport> for AppError {
+    fn from(report: ValidationReport) -> Self {
+        AppError::Validation(report)
+    }
+}
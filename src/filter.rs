@@ -0,0 +1,195 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+use crate::amap::Poi;
+use crate::hours::{self, OpenState};
+use crate::poi_types::{self, typecode_in};
+
+// 请求中按名称表示的筛选条件，如 cuisines: ["火锅", "日料"]、exclude: ["咖啡厅"]，以及按营业时间的筛选
+#[derive(Debug, Clone, Default)]
+pub struct PoiFilter {
+    cuisines: Vec<FilterTerm>,
+    exclude: Vec<FilterTerm>,
+    open_at: Option<NaiveDateTime>,   // 只保留该时刻（配置的时区）营业或营业时间未知的餐厅
+}
+
+// 单个筛选词及其对应的类型代码；分类编码表中找不到时 codes 为空，按 POI 的类型描述和名称匹配
+#[derive(Serialize, Debug, Clone)]
+pub struct FilterTerm {
+    pub term: String,
+    pub codes: Vec<String>,
+    pub match_by: &'static str,   // "category" 或 "text"
+}
+
+// 实际生效的筛选条件，随响应返回
+#[derive(Serialize, Debug, Clone)]
+pub struct AppliedFilters {
+    pub cuisines: Vec<FilterTerm>,
+    pub exclude: Vec<FilterTerm>,
+    pub amap_types: String,   // 发给高德的 types 参数
+    pub open_at: Option<String>,   // 判断营业状态的时刻，如 "2024-05-01 19:30"
+    pub post_filtered: bool,  // 是否在本地对搜索结果做了二次筛选
+    pub filtered_out: usize,  // 本地筛选去掉的 POI 数
+}
+
+impl FilterTerm {
+    fn new(term: &str) -> Self {
+        let term = term.trim().to_string();
+        let codes = poi_types::catalog().match_term(&term);
+        let match_by = if codes.is_empty() { "text" } else { "category" };
+        FilterTerm { term, codes, match_by }
+    }
+
+    fn matches(&self, poi: &Poi) -> bool {
+        if self.codes.is_empty() {
+            // 高德无法按类型表达的类别，例如 "烧烤"，在类型描述（"餐饮服务;中餐厅;特色/地方风味餐厅"）和名称中查找
+            let text = self.term.to_lowercase();
+            poi.poi_type.to_lowercase().contains(&text) || poi.name.to_lowercase().contains(&text)
+        } else {
+            typecode_in(&poi.typecode, &self.codes)
+        }
+    }
+}
+
+impl PoiFilter {
+    pub fn new(cuisines: &[String], exclude: &[String]) -> Self {
+        PoiFilter {
+            cuisines: cuisines.iter().map(|term| FilterTerm::new(term)).collect(),
+            exclude: exclude.iter().map(|term| FilterTerm::new(term)).collect(),
+            open_at: None,
+        }
+    }
+
+    pub fn with_open_at(mut self, open_at: Option<NaiveDateTime>) -> Self {
+        self.open_at = open_at;
+        self
+    }
+
+    pub fn open_at(&self) -> Option<NaiveDateTime> {
+        self.open_at
+    }
+
+    // 按营业时间标记每家餐厅的营业状态，keep 据此去掉已打烊的
+    pub fn mark_open_state(&self, pois: &mut [Poi]) {
+        let Some(at) = self.open_at else { return };
+        for poi in pois {
+            poi.open_state = Some(hours::open_state(poi.biz_ext.open_time.as_deref(), at));
+        }
+    }
+
+    // 所有菜系都能对应到类型代码时直接交给高德筛选，否则用 food_types 搜索后在本地筛选
+    pub fn amap_types(&self, food_types: &str) -> String {
+        if self.cuisines.is_empty() || self.cuisines.iter().any(|c| c.codes.is_empty()) {
+            return poi_types::resolve_type_codes(food_types).unwrap_or_else(|_| food_types.to_string());
+        }
+        let mut codes: Vec<&str> = Vec::new();
+        for code in self.cuisines.iter().flat_map(|c| &c.codes) {
+            if !codes.contains(&code.as_str()) {
+                codes.push(code);
+            }
+        }
+        codes.join("|")
+    }
+
+    pub fn needs_post_filter(&self) -> bool {
+        !self.exclude.is_empty() || self.cuisines.iter().any(|c| c.codes.is_empty()) || self.open_at.is_some()
+    }
+
+    pub fn keep(&self, poi: &Poi) -> bool {
+        (self.cuisines.is_empty() || self.cuisines.iter().any(|c| c.matches(poi)))
+            && !self.exclude.iter().any(|e| e.matches(poi))
+            && poi.open_state != Some(OpenState::Closed)
+    }
+
+    pub fn applied(&self, amap_types: String, post_filtered: bool, filtered_out: usize) -> AppliedFilters {
+        AppliedFilters {
+            cuisines: self.cuisines.clone(),
+            exclude: self.exclude.clone(),
+            open_at: self.open_at.map(|at| at.format("%Y-%m-%d %H:%M").to_string()),
+            amap_types,
+            post_filtered,
+            filtered_out,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn poi(name: &str, poi_type: &str, typecode: &str) -> Poi {
+        Poi {
+            name: name.to_string(),
+            poi_type: poi_type.to_string(),
+            typecode: typecode.to_string(),
+            ..Poi::default()
+        }
+    }
+
+    fn terms(terms: &[&str]) -> Vec<String> {
+        terms.iter().map(|term| term.to_string()).collect()
+    }
+
+    #[test]
+    fn category_and_text_terms() {
+        let filter = PoiFilter::new(&terms(&["火锅", "烧烤"]), &[]);
+        let applied = filter.applied(String::new(), true, 0);
+        assert_eq!(applied.cuisines[0].match_by, "category");
+        assert_eq!(applied.cuisines[0].codes, ["050117"]);
+        assert_eq!(applied.cuisines[1].match_by, "text");
+        assert!(applied.cuisines[1].codes.is_empty());
+
+        assert!(filter.keep(&poi("海底捞", "餐饮服务;中餐厅;火锅店", "050117")));
+        assert!(filter.keep(&poi("老王烧烤", "餐饮服务;中餐厅;中餐厅", "050100")));
+        assert!(filter.keep(&poi("串吧", "餐饮服务;中餐厅;烧烤", "050118")));
+        assert!(!filter.keep(&poi("星巴克", "餐饮服务;咖啡厅;星巴克咖啡", "050501")));
+    }
+
+    #[test]
+    fn excludes_by_category_and_text() {
+        let filter = PoiFilter::new(&[], &terms(&["咖啡", "肯德基"]));
+        assert!(!filter.keep(&poi("星巴克", "餐饮服务;咖啡厅;星巴克咖啡", "050501")));
+        assert!(!filter.keep(&poi("肯德基(开发区店)", "餐饮服务;快餐厅;肯德基", "050301")));
+        assert!(filter.keep(&poi("海底捞", "餐饮服务;中餐厅;火锅店", "050117")));
+    }
+
+    #[test]
+    fn amap_types_and_post_filter() {
+        // 所有菜系都有类型代码时交给高德筛选
+        let filter = PoiFilter::new(&terms(&["火锅", "日料", "火锅店"]), &[]);
+        assert_eq!(filter.amap_types("050000"), "050117|050202");
+        assert!(!filter.needs_post_filter());
+
+        // 有无法对应类型代码的菜系时按 food_types 搜索，在本地筛选
+        let filter = PoiFilter::new(&terms(&["火锅", "烧烤"]), &[]);
+        assert_eq!(filter.amap_types("中餐厅"), "050100");
+        assert!(filter.needs_post_filter());
+
+        let filter = PoiFilter::new(&[], &terms(&["咖啡"]));
+        assert_eq!(filter.amap_types("050000"), "050000");
+        assert!(filter.needs_post_filter());
+
+        let filter = PoiFilter::default();
+        assert!(!filter.needs_post_filter());
+        assert!(filter.with_open_at(hours::parse_open_at("2024-05-01 19:30", hours::now("+08:00"))).needs_post_filter());
+    }
+
+    #[test]
+    fn drops_closed_pois() {
+        let at = hours::parse_open_at("2024-05-01 23:30", hours::now("+08:00"));
+        let filter = PoiFilter::default().with_open_at(at);
+        let mut pois = vec![
+            poi("早餐店", "", "050100"),
+            poi("烧烤", "", "050100"),
+            poi("未知", "", "050100"),
+        ];
+        pois[0].biz_ext.open_time = Some("06:00-10:00".to_string());
+        pois[1].biz_ext.open_time = Some("18:00-次日02:00".to_string());
+        filter.mark_open_state(&mut pois);
+        let states: Vec<Option<OpenState>> = pois.iter().map(|poi| poi.open_state).collect();
+        assert_eq!(states, [Some(OpenState::Closed), Some(OpenState::Open), Some(OpenState::Unknown)]);
+        let kept: Vec<&str> = pois.iter().filter(|poi| filter.keep(poi)).map(|poi| poi.name.as_str()).collect();
+        assert_eq!(kept, ["烧烤", "未知"]);
+        assert_eq!(filter.applied(String::new(), true, 1).open_at.as_deref(), Some("2024-05-01 23:30"));
+    }
+}
@@ -0,0 +1,422 @@
+use async_trait::async_trait;
+use colored::*;
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::Value;
+use crate::amap::{get_json, parse_coordinates, AmapError};
+use crate::config::Config;
+use crate::error::AppError;
+use crate::i18n::Msg;
+use crate::retry::RetryPolicy;
+
+// 可用的地理编码实现名称，对应 Config.geocoders 中的取值
+pub const GEOCODER_NAMES: [&str; 2] = ["inputtips", "geocode"];
+
+// 地理编码候选结果
+#[derive(Serialize, Debug, Clone)]
+pub struct Candidate {
+    pub id: String,          // 高德POI ID；结构化地址没有POI时用坐标生成
+    pub name: String,
+    pub district: String,
+    pub adcode: String,
+    pub address: String,
+    pub longitude: f64,
+    pub latitude: f64,
+    pub source: &'static str, // 产生该候选的地理编码实现
+}
+
+// 地理编码服务：把地点描述解析为候选坐标列表
+#[async_trait(?Send)]
+pub trait Geocoder {
+    fn name(&self) -> &'static str;
+    async fn resolve(&self, query: &str, city: &str) -> Result<Vec<Candidate>, AmapError>;
+}
+
+// 高德输入提示 /v3/assistant/inputtips，适合POI名称（学校、商场等）
+pub struct AmapInputTips {
+    client: Client,
+    retry: RetryPolicy,
+    api_key: String,
+    user_agent: String,
+}
+
+#[async_trait(?Send)]
+impl Geocoder for AmapInputTips {
+    fn name(&self) -> &'static str {
+        "inputtips"
+    }
+
+    async fn resolve(&self, query: &str, city: &str) -> Result<Vec<Candidate>, AmapError> {
+        let params = [("key", self.api_key.as_str()), ("keywords", query), ("city", city)];
+        let data = get_json(&self.client, &self.retry, "https://restapi.amap.com/v3/assistant/inputtips", &params, &self.user_agent).await?;
+
+        // 没有坐标的提示（公交线路、纯关键词等）直接跳过
+        let candidates = data["tips"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|tip| {
+                let (longitude, latitude) = parse_location(tip["location"].as_str()?)?;
+                let id = text(&tip["id"]);
+                Some(Candidate {
+                    id: if id.is_empty() { coordinate_id(longitude, latitude) } else { id },
+                    name: text(&tip["name"]),
+                    district: text(&tip["district"]),
+                    adcode: text(&tip["adcode"]),
+                    address: text(&tip["address"]),
+                    longitude,
+                    latitude,
+                    source: self.name(),
+                })
+            })
+            .collect();
+        Ok(candidates)
+    }
+}
+
+// 高德地理编码 /v3/geocode/geo，适合结构化地址（省市区+街道门牌）
+pub struct AmapGeocode {
+    client: Client,
+    retry: RetryPolicy,
+    api_key: String,
+    user_agent: String,
+}
+
+#[async_trait(?Send)]
+impl Geocoder for AmapGeocode {
+    fn name(&self) -> &'static str {
+        "geocode"
+    }
+
+    async fn resolve(&self, query: &str, city: &str) -> Result<Vec<Candidate>, AmapError> {
+        let params = [("key", self.api_key.as_str()), ("address", query), ("city", city)];
+        let data = get_json(&self.client, &self.retry, "https://restapi.amap.com/v3/geocode/geo", &params, &self.user_agent).await?;
+
+        let candidates = data["geocodes"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|geo| {
+                let (longitude, latitude) = parse_location(geo["location"].as_str()?)?;
+                Some(Candidate {
+                    id: coordinate_id(longitude, latitude),
+                    name: text(&geo["formatted_address"]),
+                    district: format!("{}{}{}", text(&geo["province"]), text(&geo["city"]), text(&geo["district"])),
+                    adcode: text(&geo["adcode"]),
+                    address: text(&geo["formatted_address"]),
+                    longitude,
+                    latitude,
+                    source: self.name(),
+                })
+            })
+            .collect();
+        Ok(candidates)
+    }
+}
+
+// 依次尝试多个实现，返回第一个非空结果；某个实现出错时继续尝试下一个
+pub struct GeocoderChain {
+    providers: Vec<Box<dyn Geocoder>>,
+}
+
+#[async_trait(?Send)]
+impl Geocoder for GeocoderChain {
+    fn name(&self) -> &'static str {
+        "chain"
+    }
+
+    async fn resolve(&self, query: &str, city: &str) -> Result<Vec<Candidate>, AmapError> {
+        let mut last_error = None;
+        for provider in &self.providers {
+            match provider.resolve(query, city).await {
+                Ok(candidates) if !candidates.is_empty() => return Ok(candidates),
+                Ok(_) => println!("⚠️  {} 未找到 {}，尝试下一个地理编码服务", provider.name(), query),
+                Err(e) => {
+                    println!("⚠️  {} 调用失败: {}，尝试下一个地理编码服务", provider.name(), e);
+                    last_error = Some(e);
+                }
+            }
+        }
+        match last_error {
+            Some(e) => Err(e),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+// 根据配置构建地理编码服务，每次请求构建以便使用热重载后的密钥
+pub fn geocoder_from_config(client: &Client, config: &Config, retry: &RetryPolicy) -> Box<dyn Geocoder> {
+    let user_agent = format!("{}-geo-service", config.username);
+    let mut providers: Vec<Box<dyn Geocoder>> = config
+        .geocoders
+        .iter()
+        .filter_map(|name| -> Option<Box<dyn Geocoder>> {
+            match name.as_str() {
+                "inputtips" => Some(Box::new(AmapInputTips {
+                    client: client.clone(),
+                    retry: retry.clone(),
+                    api_key: config.api_key.clone(),
+                    user_agent: user_agent.clone(),
+                })),
+                "geocode" => Some(Box::new(AmapGeocode {
+                    client: client.clone(),
+                    retry: retry.clone(),
+                    api_key: config.api_key.clone(),
+                    user_agent: user_agent.clone(),
+                })),
+                _ => None,
+            }
+        })
+        .collect();
+
+    if providers.len() == 1 {
+        providers.remove(0)
+    } else {
+        Box::new(GeocoderChain { providers })
+    }
+}
+
+// 客户端指定地点的三种方式，优先级：坐标 > 候选ID > 文本
+pub enum LocationQuery<'a> {
+    Coordinates { longitude: f64, latitude: f64, name: &'a str },
+    CandidateId(&'a str),
+    Text(&'a str),
+}
+
+// 地点解析结果：唯一确定，或存在多个同样匹配的候选需要客户端选择
+enum Resolution {
+    Resolved(Candidate),
+    Ambiguous(Vec<Candidate>),
+}
+
+// 距离第一个候选超过该值（米）的同名候选才视为不同地点
+const AMBIGUITY_DISTANCE: f64 = 1000.0;
+
+// 存在多个同样匹配的候选时返回 AppError::AmbiguousLocation，由客户端选择候选ID后重新请求
+pub async fn resolve_location(
+    client: &Client,
+    config: &Config,
+    retry: &RetryPolicy,
+    query: LocationQuery<'_>,
+) -> Result<Candidate, AppError> {
+    match query {
+        LocationQuery::Coordinates { longitude, latitude, name } => {
+            check_coordinates("longitude/latitude", longitude, latitude)?;
+            println!("📍 使用请求坐标: {:.6}, {:.6}", longitude, latitude);
+            Ok(Candidate {
+                id: coordinate_id(longitude, latitude),
+                name: if name.is_empty() { format!("{:.6},{:.6}", longitude, latitude) } else { name.to_string() },
+                district: String::new(),
+                adcode: String::new(),
+                address: String::new(),
+                longitude,
+                latitude,
+                source: "coordinates",
+            })
+        }
+        LocationQuery::CandidateId(id) => {
+            println!("🔍 使用候选地点: {}", id.green());
+            candidate_by_id(client, config, retry, id).await
+        }
+        LocationQuery::Text(text) => {
+            println!("🔍 查询地点坐标: {}", text.green());
+            let geocoder = geocoder_from_config(client, config, retry);
+            let candidates = geocoder.resolve(text, &config.city).await.map_err(AppError::Geocoding)?;
+            if candidates.is_empty() {
+                return Err(AppError::LocationNotFound(text.to_string()));
+            }
+            match disambiguate(text, candidates) {
+                Resolution::Resolved(c) => {
+                    println!("✅ 坐标解析成功: {:.6}, {:.6} ({})", c.longitude, c.latitude, c.name);
+                    Ok(c)
+                }
+                Resolution::Ambiguous(cs) => {
+                    println!("⚠️  {} 匹配到 {} 个不同地点，需要客户端选择", text, cs.len());
+                    Err(AppError::AmbiguousLocation(cs))
+                }
+            }
+        }
+    }
+}
+
+// 名称包含查询词的候选视为强匹配；多个强匹配且相距较远时认为有歧义
+// 名称与查询词完全一致的候选只有一个时直接采用
+fn disambiguate(query: &str, candidates: Vec<Candidate>) -> Resolution {
+    let query = query.trim();
+    let exact: Vec<&Candidate> = candidates.iter().filter(|c| c.name == query).collect();
+    if exact.len() == 1 {
+        return Resolution::Resolved(exact[0].clone());
+    }
+
+    let strong: Vec<Candidate> = candidates
+        .iter()
+        .filter(|c| c.name.contains(query))
+        .cloned()
+        .collect();
+    let first = strong.first().unwrap_or(&candidates[0]).clone();
+    let spread = strong
+        .iter()
+        .any(|c| distance_meters((first.longitude, first.latitude), (c.longitude, c.latitude)) > AMBIGUITY_DISTANCE);
+
+    if strong.len() > 1 && spread {
+        Resolution::Ambiguous(strong)
+    } else {
+        Resolution::Resolved(first)
+    }
+}
+
+// 根据候选ID取回坐标："geo:" 开头的ID自带坐标，其余为高德POI ID
+async fn candidate_by_id(client: &Client, config: &Config, retry: &RetryPolicy, id: &str) -> Result<Candidate, AppError> {
+    if let Some(coords) = id.strip_prefix("geo:") {
+        let (longitude, latitude) = parse_location(coords)
+            .ok_or_else(|| AppError::invalid_msg("candidate_id", Msg::new("validation.candidate_id").arg("id", id)))?;
+        check_coordinates("candidate_id", longitude, latitude)?;
+        return Ok(Candidate {
+            id: id.to_string(),
+            name: coords.to_string(),
+            district: String::new(),
+            adcode: String::new(),
+            address: String::new(),
+            longitude,
+            latitude,
+            source: "coordinates",
+        });
+    }
+
+    let params = [("key", config.api_key.as_str()), ("id", id)];
+    let user_agent = format!("{}-geo-service", config.username);
+    let data = get_json(client, retry, "https://restapi.amap.com/v3/place/detail", &params, &user_agent)
+        .await
+        .map_err(AppError::Geocoding)?;
+    let poi = data["pois"]
+        .as_array()
+        .and_then(|pois| pois.first())
+        .ok_or_else(|| AppError::LocationNotFound(id.to_string()))?;
+    let (longitude, latitude) = poi["location"]
+        .as_str()
+        .and_then(parse_location)
+        .ok_or_else(|| AppError::Geocoding(AmapError::InvalidResponse("无法解析候选地点坐标".to_string())))?;
+    Ok(Candidate {
+        id: id.to_string(),
+        name: text(&poi["name"]),
+        district: text(&poi["adname"]),
+        adcode: text(&poi["adcode"]),
+        address: text(&poi["address"]),
+        longitude,
+        latitude,
+        source: "poi",
+    })
+}
+
+fn check_coordinates(field: &str, longitude: f64, latitude: f64) -> Result<(), AppError> {
+    if (-180.0..=180.0).contains(&longitude) && (-90.0..=90.0).contains(&latitude) {
+        return Ok(());
+    }
+    Err(AppError::invalid_msg(
+        field,
+        Msg::new("validation.coordinates_range").arg("longitude", longitude).arg("latitude", latitude),
+    ))
+}
+
+// 球面距离（米），用于判断同名候选是否为同一地点
+pub fn distance_meters(a: (f64, f64), b: (f64, f64)) -> f64 {
+    const EARTH_RADIUS: f64 = 6_371_000.0;
+    let (lon1, lat1) = (a.0.to_radians(), a.1.to_radians());
+    let (lon2, lat2) = (b.0.to_radians(), b.1.to_radians());
+    let h = ((lat2 - lat1) / 2.0).sin().powi(2)
+        + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * h.sqrt().asin()
+}
+
+// 高德坐标格式 "经度,纬度"
+fn parse_location(location: &str) -> Option<(f64, f64)> {
+    parse_coordinates(location).map(|c| (c.longitude, c.latitude))
+}
+
+fn coordinate_id(longitude: f64, latitude: f64) -> String {
+    format!("geo:{:.6},{:.6}", longitude, latitude)
+}
+
+// 高德对空字段返回 [] 而不是空字符串
+fn text(value: &Value) -> String {
+    value.as_str().unwrap_or_default().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(name: &str, longitude: f64, latitude: f64) -> Candidate {
+        Candidate {
+            id: coordinate_id(longitude, latitude),
+            name: name.to_string(),
+            district: String::new(),
+            adcode: String::new(),
+            address: String::new(),
+            longitude,
+            latitude,
+            source: "inputtips",
+        }
+    }
+
+    fn names(resolution: Resolution) -> (bool, Vec<String>) {
+        match resolution {
+            Resolution::Resolved(c) => (false, vec![c.name]),
+            Resolution::Ambiguous(cs) => (true, cs.into_iter().map(|c| c.name).collect()),
+        }
+    }
+
+    #[test]
+    fn disambiguates_candidates() {
+        // 唯一的完全匹配直接采用，即使有其他相距很远的同名候选
+        let candidates = vec![
+            candidate("万达广场(高新店)", 121.53, 38.86),
+            candidate("万达广场", 121.63, 38.92),
+        ];
+        assert_eq!(names(disambiguate(" 万达广场 ", candidates)), (false, vec!["万达广场".to_string()]));
+
+        // 多个相距较远的强匹配需要客户端选择，弱匹配不参与
+        let candidates = vec![
+            candidate("万达广场(高新店)", 121.53, 38.86),
+            candidate("万达广场(甘井子店)", 121.58, 38.95),
+            candidate("星海广场", 121.58, 38.88),
+        ];
+        let (ambiguous, names_) = names(disambiguate("万达广场", candidates));
+        assert!(ambiguous);
+        assert_eq!(names_, ["万达广场(高新店)", "万达广场(甘井子店)"]);
+
+        // 强匹配都在 1 公里内时视为同一地点，取第一个
+        let candidates = vec![
+            candidate("大连理工大学-东门", 121.5260, 38.8820),
+            candidate("大连理工大学-西门", 121.5220, 38.8810),
+        ];
+        assert_eq!(names(disambiguate("大连理工大学", candidates)), (false, vec!["大连理工大学-东门".to_string()]));
+
+        // 没有强匹配时取高德排序的第一个
+        let candidates = vec![candidate("软件园", 121.52, 38.86), candidate("腾飞园区", 121.53, 38.87)];
+        assert_eq!(names(disambiguate("大连软件园", candidates)), (false, vec!["软件园".to_string()]));
+    }
+
+    fn field_error(result: Result<Candidate, AppError>) -> String {
+        match result {
+            Err(AppError::Validation(report)) => report.to_string(),
+            other => panic!("unexpected result: {:?}", other.map(|c| c.id)),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn coordinate_candidate_ids() {
+        let (client, config) = (Client::new(), Config::default());
+        let retry = RetryPolicy::from_config(&config);
+
+        let c = candidate_by_id(&client, &config, &retry, "geo:121.526000,38.882000").await.unwrap();
+        assert_eq!((c.longitude, c.latitude, c.source), (121.526, 38.882, "coordinates"));
+
+        let error = field_error(candidate_by_id(&client, &config, &retry, "geo:200.0,38.88").await);
+        assert!(error.contains("candidate_id") && error.contains("200"), "{}", error);
+        let error = field_error(candidate_by_id(&client, &config, &retry, "geo:121.52,-95").await);
+        assert!(error.contains("candidate_id"), "{}", error);
+        let error = field_error(candidate_by_id(&client, &config, &retry, "geo:abc").await);
+        assert!(error.contains("candidate_id"), "{}", error);
+    }
+}
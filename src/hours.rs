@@ -0,0 +1,375 @@
+use chrono::{Datelike, FixedOffset, NaiveDateTime, NaiveTime, Timelike, Utc};
+use serde::Serialize;
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+// 按营业时间判断的营业状态，随 POI 返回并写入提示词
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OpenState {
+    Open,
+    Closed,
+    Unknown,   // 没有营业时间或无法解析
+}
+
+// 一周七天（周一为 0）各自的营业时段，单位为当天 0 点起的分钟数；跨夜时段的结束时间大于 1440
+// 某天为 None 表示营业时间中没有提到这一天
+#[derive(Debug, Clone, Default)]
+pub struct OpeningHours {
+    week: [Option<Vec<(u32, u32)>>; 7],
+}
+
+impl OpeningHours {
+    // 解析高德的营业时间，如 "10:00-22:00"、"周一至周五 11:00-14:00,17:00-21:00；周六至周日 10:00-次日02:00"、"24小时营业"
+    // 以分号、换行或新的星期范围分段，每段的星期范围省略时表示每天；后面的段覆盖前面的段，无法解析时返回 None
+    pub fn parse(text: &str) -> Option<OpeningHours> {
+        let text = normalize(text);
+        let mut hours = OpeningHours::default();
+        let mut explicit_days = false;
+        for segment in text.split([';', '\n']).flat_map(split_groups) {
+            // 节假日的营业时间无法判断，忽略该段
+            if segment.contains("节假日") || segment.contains("法定") {
+                continue;
+            }
+            let days = parse_days(segment);
+            let spans = if segment.contains("24小时") || segment.contains("全天") {
+                vec![(0, MINUTES_PER_DAY)]
+            } else {
+                parse_spans(segment)
+            };
+            let closed = spans.is_empty() && ["休息", "休业", "闭店", "不营业"].iter().any(|word| segment.contains(word));
+            if spans.is_empty() && !closed {
+                continue;
+            }
+            explicit_days |= days.is_some();
+            for (day, selected) in days.unwrap_or([true; 7]).into_iter().enumerate() {
+                if selected {
+                    hours.week[day] = Some(spans.clone());
+                }
+            }
+        }
+        if hours.week.iter().all(Option::is_none) {
+            return None;
+        }
+        // "周一至周五 09:00-18:00" 这类只列出部分星期的，没有提到的日子视为不营业
+        if explicit_days {
+            for day in hours.week.iter_mut() {
+                day.get_or_insert_with(Vec::new);
+            }
+        }
+        Some(hours)
+    }
+
+    pub fn is_open(&self, at: NaiveDateTime) -> bool {
+        let day = at.weekday().num_days_from_monday() as usize;
+        let minute = at.hour() * 60 + at.minute();
+        let today = self.week[day].iter().flatten().any(|&(start, end)| start <= minute && minute < end);
+        // 前一天跨夜的时段，如 18:00-次日02:00
+        let yesterday = self.week[(day + 6) % 7]
+            .iter()
+            .flatten()
+            .any(|&(_, end)| minute + MINUTES_PER_DAY < end);
+        today || yesterday
+    }
+}
+
+// 按营业时间判断某一时刻的营业状态
+pub fn open_state(open_time: Option<&str>, at: NaiveDateTime) -> OpenState {
+    match open_time.and_then(OpeningHours::parse) {
+        Some(hours) if hours.is_open(at) => OpenState::Open,
+        Some(_) => OpenState::Closed,
+        None => OpenState::Unknown,
+    }
+}
+
+// 统一全角符号和各种连接符，"次日" 替换为 '+' 标记跨夜的结束时间
+fn normalize(text: &str) -> String {
+    text.replace("星期", "周")
+        .replace("礼拜", "周")
+        .replace("次日", "+")
+        .chars()
+        .map(|c| match c {
+            '：' => ':',
+            '；' => ';',
+            '，' | '、' => ',',
+            '－' | '—' | '–' | '~' | '～' | '至' | '到' => '-',
+            _ => c,
+        })
+        .collect()
+}
+
+// "周一至周五 10:00-22:00 周六至周日 09:00-23:00" 这类没有分号的写法：
+// 已经出现营业时间后再遇到星期（或节假日）时开始新的一段；"周一,周三 10:00-22:00" 中的星期属于同一段
+fn split_groups(segment: &str) -> Vec<&str> {
+    const GROUP_STARTS: [&str; 7] = ["工作日", "周末", "每天", "每日", "双休日", "节假日", "法定"];
+    const HOURS_MARKS: [&str; 7] = [":", "24小时", "全天", "休息", "休业", "闭店", "不营业"];
+    let mut groups = Vec::new();
+    let mut start = 0;
+    for (i, _) in segment.char_indices() {
+        let rest = &segment[i..];
+        let mut chars = rest.chars();
+        let weekday = chars.next() == Some('周') && chars.next().and_then(weekday_index).is_some();
+        if !(weekday || GROUP_STARTS.iter().any(|word| rest.starts_with(word))) {
+            continue;
+        }
+        let current = &segment[start..i];
+        if HOURS_MARKS.iter().any(|mark| current.contains(mark)) {
+            groups.push(current);
+            start = i;
+        }
+    }
+    groups.push(&segment[start..]);
+    groups
+}
+
+fn weekday_index(c: char) -> Option<usize> {
+    "一二三四五六日".chars().position(|d| d == c).or(match c {
+        '天' | '七' => Some(6),
+        _ => None,
+    })
+}
+
+// 段中的星期范围，如 "周一-周五"、"周一-五"、"周一,周三"、"工作日"、"周末"；没有时返回 None
+fn parse_days(segment: &str) -> Option<[bool; 7]> {
+    let mut days = [false; 7];
+    let mut found = false;
+    if ["每天", "每日", "天天", "全周"].iter().any(|word| segment.contains(word)) {
+        return Some([true; 7]);
+    }
+    if segment.contains("工作日") {
+        days[..5].fill(true);
+        found = true;
+    }
+    if segment.contains("周末") || segment.contains("双休日") {
+        days[5..].fill(true);
+        found = true;
+    }
+    let chars: Vec<char> = segment.chars().collect();
+    let mut i = 0;
+    while i + 1 < chars.len() {
+        let Some(start) = (chars[i] == '周').then(|| weekday_index(chars[i + 1])).flatten() else {
+            i += 1;
+            continue;
+        };
+        i += 2;
+        let mut end = start;
+        if chars.get(i) == Some(&'-') {
+            let next = if chars.get(i + 1) == Some(&'周') { i + 2 } else { i + 1 };
+            if let Some(day) = chars.get(next).and_then(|&c| weekday_index(c)) {
+                end = day;
+                i = next + 1;
+            }
+        }
+        // 周五-周一 这类跨周末的范围
+        let mut day = start;
+        loop {
+            days[day] = true;
+            if day == end {
+                break;
+            }
+            day = (day + 1) % 7;
+        }
+        found = true;
+    }
+    found.then_some(days)
+}
+
+// 段中的所有时段，如 "11:00-14:00,17:00-21:00"、"18:00-+02:00"；结束时间不晚于开始时间时视为跨夜
+fn parse_spans(segment: &str) -> Vec<(u32, u32)> {
+    let chars: Vec<char> = segment.chars().collect();
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let Some((start, next)) = parse_time(&chars, i) else {
+            i += 1;
+            continue;
+        };
+        let mut j = skip_spaces(&chars, next);
+        if chars.get(j) != Some(&'-') {
+            i = next;
+            continue;
+        }
+        j = skip_spaces(&chars, j + 1);
+        let next_day = chars.get(j) == Some(&'+');
+        if next_day {
+            j += 1;
+        }
+        let Some((mut end, after)) = parse_time(&chars, j) else {
+            i = j;
+            continue;
+        };
+        if next_day || end <= start {
+            end += MINUTES_PER_DAY;
+        }
+        spans.push((start, end));
+        i = after;
+    }
+    spans
+}
+
+// 解析 "H:MM" 或 "HH:MM"，返回分钟数和之后的位置；24:00 表示当天结束
+fn parse_time(chars: &[char], i: usize) -> Option<(u32, usize)> {
+    let digits = chars[i..].iter().take_while(|c| c.is_ascii_digit()).count();
+    if !(1..=2).contains(&digits) || (i > 0 && chars[i - 1].is_ascii_digit()) {
+        return None;
+    }
+    let colon = i + digits;
+    if chars.get(colon) != Some(&':') {
+        return None;
+    }
+    let minute_digits: String = chars.get(colon + 1..colon + 3)?.iter().collect();
+    let hour: u32 = chars[i..colon].iter().collect::<String>().parse().ok()?;
+    let minute: u32 = minute_digits.parse().ok()?;
+    if hour > 24 || minute > 59 || (hour == 24 && minute > 0) {
+        return None;
+    }
+    Some((hour * 60 + minute, colon + 3))
+}
+
+fn skip_spaces(chars: &[char], mut i: usize) -> usize {
+    while chars.get(i).is_some_and(|c| c.is_whitespace()) {
+        i += 1;
+    }
+    i
+}
+
+// 解析时区，格式为 "+08:00"、"-05:30"、"UTC+8" 或 "UTC"
+pub fn parse_offset(text: &str) -> Option<FixedOffset> {
+    let text = text.trim();
+    let rest = text.strip_prefix("UTC").or_else(|| text.strip_prefix("GMT")).unwrap_or(text);
+    if rest.is_empty() || rest == "Z" {
+        return FixedOffset::east_opt(0);
+    }
+    let sign = match rest.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let (hours, minutes) = rest[1..].split_once(':').unwrap_or((&rest[1..], "0"));
+    let hours: i32 = hours.parse().ok()?;
+    let minutes: i32 = minutes.parse().ok()?;
+    if hours > 14 || minutes > 59 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+// 指定时区的当前时间；时区已在配置校验时检查过，无效时按 UTC
+pub fn now(timezone: &str) -> NaiveDateTime {
+    let offset = parse_offset(timezone).unwrap_or(FixedOffset::east_opt(0).unwrap());
+    Utc::now().with_timezone(&offset).naive_local()
+}
+
+// 解析请求的 open_at，格式为 "2024-05-01 19:30" 或只有时间 "19:30"（当天）
+pub fn parse_open_at(text: &str, now: NaiveDateTime) -> Option<NaiveDateTime> {
+    let text = text.trim();
+    ["%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .or_else(|| {
+            let time = NaiveTime::parse_from_str(text, "%H:%M").ok()?;
+            Some(now.date().and_time(time))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-05-01 是周三
+    fn at(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn state(open_time: &str, time: &str) -> OpenState {
+        open_state(Some(open_time), at(time))
+    }
+
+    #[test]
+    fn single_range() {
+        assert_eq!(state("10:00-22:00", "2024-05-01 12:00"), OpenState::Open);
+        assert_eq!(state("10:00-22:00", "2024-05-01 22:00"), OpenState::Closed);
+        assert_eq!(state("10：00－22：00", "2024-05-01 21:59"), OpenState::Open);
+        assert_eq!(state("11:00-14:00,17:00-21:00", "2024-05-01 15:00"), OpenState::Closed);
+        assert_eq!(state("11:00-14:00,17:00-21:00", "2024-05-01 18:00"), OpenState::Open);
+    }
+
+    #[test]
+    fn weekday_ranges() {
+        let hours = "周一至周五 11:00-14:00,17:00-21:00；周六至周日 10:00-22:00";
+        assert_eq!(state(hours, "2024-05-01 18:00"), OpenState::Open);
+        assert_eq!(state(hours, "2024-05-01 15:00"), OpenState::Closed);
+        assert_eq!(state(hours, "2024-05-04 15:00"), OpenState::Open);
+        // 只列出部分星期时其余日子不营业
+        assert_eq!(state("周一-周五 09:00-18:00", "2024-05-04 12:00"), OpenState::Closed);
+        assert_eq!(state("工作日 09:00-18:00", "2024-05-01 12:00"), OpenState::Open);
+        assert_eq!(state("周一至周日 10:00～22:00；周二休息", "2024-04-30 12:00"), OpenState::Closed);
+        assert_eq!(state("周一至周日 10:00～22:00；周二休息", "2024-05-01 12:00"), OpenState::Open);
+    }
+
+    #[test]
+    fn multiple_groups_without_separator() {
+        let hours = "周一至周五 10:00-22:00 周六至周日 09:00-23:00";
+        assert_eq!(state(hours, "2024-04-29 22:30"), OpenState::Closed);
+        assert_eq!(state(hours, "2024-05-04 22:30"), OpenState::Open);
+        assert_eq!(state(hours, "2024-05-04 09:30"), OpenState::Open);
+        assert_eq!(state(hours, "2024-05-01 09:30"), OpenState::Closed);
+        let hours = "周一-周五 10:00-22:00,周六-周日 09:00-23:00";
+        assert_eq!(state(hours, "2024-04-29 22:30"), OpenState::Closed);
+        assert_eq!(state(hours, "2024-05-05 22:30"), OpenState::Open);
+        // 同一段中逗号分隔的星期
+        let hours = "周一,周三 10:00-22:00";
+        assert_eq!(state(hours, "2024-05-01 12:00"), OpenState::Open);
+        assert_eq!(state(hours, "2024-04-30 12:00"), OpenState::Closed);
+    }
+
+    #[test]
+    fn overnight() {
+        let hours = "周一至周五 11:00-21:00；周六至周日 10:00-次日02:00";
+        assert_eq!(state(hours, "2024-05-05 01:00"), OpenState::Open);
+        assert_eq!(state(hours, "2024-05-06 01:00"), OpenState::Open);
+        assert_eq!(state(hours, "2024-05-07 01:00"), OpenState::Closed);
+        assert_eq!(state("18:00-02:00", "2024-05-02 01:30"), OpenState::Open);
+        assert_eq!(state("18:00-02:00", "2024-05-02 02:30"), OpenState::Closed);
+    }
+
+    #[test]
+    fn all_day() {
+        assert_eq!(state("24小时营业", "2024-05-04 03:00"), OpenState::Open);
+        assert_eq!(state("00:00-24:00", "2024-05-04 23:59"), OpenState::Open);
+    }
+
+    #[test]
+    fn holidays_are_ignored() {
+        let hours = "周一至周日 10:00-22:00；节假日 09:00-23:00";
+        assert_eq!(state(hours, "2024-05-01 09:30"), OpenState::Closed);
+        let hours = "周一至周日 10:00-22:00 节假日 09:00-23:00";
+        assert_eq!(state(hours, "2024-05-01 22:30"), OpenState::Closed);
+    }
+
+    #[test]
+    fn unknown_hours() {
+        assert_eq!(state("营业中", "2024-05-01 12:00"), OpenState::Unknown);
+        assert_eq!(open_state(None, at("2024-05-01 12:00")), OpenState::Unknown);
+    }
+
+    #[test]
+    fn offsets() {
+        assert_eq!(parse_offset("+08:00"), FixedOffset::east_opt(8 * 3600));
+        assert_eq!(parse_offset("-05:30"), FixedOffset::east_opt(-(5 * 3600 + 30 * 60)));
+        assert_eq!(parse_offset("UTC+8"), FixedOffset::east_opt(8 * 3600));
+        assert_eq!(parse_offset("UTC"), FixedOffset::east_opt(0));
+        assert_eq!(parse_offset("Asia/Shanghai"), None);
+        assert_eq!(parse_offset("+15:00"), None);
+    }
+
+    #[test]
+    fn open_at() {
+        let now = at("2024-05-01 10:00");
+        assert_eq!(parse_open_at("19:30", now), Some(at("2024-05-01 19:30")));
+        assert_eq!(parse_open_at("2024-05-02 08:15", now), Some(at("2024-05-02 08:15")));
+        assert_eq!(parse_open_at("2024-05-02T08:15", now), Some(at("2024-05-02 08:15")));
+        assert_eq!(parse_open_at("tonight", now), None);
+        assert_eq!(parse_open_at("25:00", now), None);
+    }
+}
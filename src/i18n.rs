@@ -0,0 +1,198 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderMap, HeaderValue, ACCEPT_LANGUAGE, CONTENT_LANGUAGE};
+use actix_web::middleware::Next;
+use actix_web::{HttpMessage, HttpRequest};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::sync::OnceLock;
+
+use crate::error::AppError;
+use crate::retry::AttemptLog;
+
+// 消息目录，编译进二进制；键为 "段.键"，例如 "error.LOCATION_NOT_FOUND"
+const ZH_CN_CATALOG: &str = include_str!("../locales/zh-CN.toml");
+const EN_CATALOG: &str = include_str!("../locales/en.toml");
+
+// 支持的语言，默认简体中文
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Lang {
+    #[default]
+    #[serde(rename = "zh-CN")]
+    ZhCn,
+    #[serde(rename = "en")]
+    En,
+}
+
+pub const LANGS: [Lang; 2] = [Lang::ZhCn, Lang::En];
+
+impl Lang {
+    pub fn code(self) -> &'static str {
+        match self {
+            Lang::ZhCn => "zh-CN",
+            Lang::En => "en",
+        }
+    }
+
+    // 按 BCP 47 主标签匹配：zh、zh-CN、zh-Hans 都视为简体中文，en-US 等视为英文
+    pub fn parse(tag: &str) -> Option<Lang> {
+        let tag = tag.trim().to_ascii_lowercase();
+        match tag.split(['-', '_']).next() {
+            Some("zh") => Some(Lang::ZhCn),
+            Some("en") => Some(Lang::En),
+            _ => None,
+        }
+    }
+
+    // 解析 Accept-Language，按 q 值从高到低取第一个支持的语言
+    pub fn negotiate(accept_language: &str) -> Option<Lang> {
+        let mut ranges: Vec<(f32, &str)> = accept_language
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';');
+                let tag = parts.next()?.trim();
+                let q = parts
+                    .find_map(|p| p.trim().strip_prefix("q="))
+                    .map(|q| q.trim().parse::<f32>().unwrap_or(0.0))
+                    .unwrap_or(1.0);
+                (q > 0.0 && !tag.is_empty()).then_some((q, tag))
+            })
+            .collect();
+        // 稳定排序，q 值相同时保持客户端给出的顺序
+        ranges.sort_by(|a, b| b.0.total_cmp(&a.0));
+        ranges.into_iter().find_map(|(_, tag)| Lang::parse(tag))
+    }
+
+    fn from_headers(headers: &HeaderMap) -> Lang {
+        headers
+            .get(ACCEPT_LANGUAGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(Lang::negotiate)
+            .unwrap_or_default()
+    }
+
+    // 请求的语言：请求体中的 lang 优先，其次 Accept-Language，都没有时为简体中文
+    // 结果记录在请求扩展中，错误响应由 localize_errors 按同一语言生成
+    pub fn from_request(req: &HttpRequest, lang: Option<&str>) -> Result<Lang, AppError> {
+        let lang = match lang.map(str::trim).filter(|l| !l.is_empty()) {
+            Some(tag) => Lang::parse(tag).ok_or_else(|| {
+                let available: Vec<&str> = LANGS.iter().map(|l| l.code()).collect();
+                AppError::invalid_msg("lang", Msg::new("validation.unknown_lang").arg("value", tag).arg("available", format!("{:?}", available)))
+            })?,
+            None => Lang::from_headers(req.headers()),
+        };
+        req.extensions_mut().insert(lang);
+        Ok(lang)
+    }
+}
+
+// 带参数的目录消息，需要时再按语言生成文本
+#[derive(Debug, Clone)]
+pub struct Msg {
+    key: &'static str,
+    args: Vec<(&'static str, String)>,
+}
+
+impl Msg {
+    pub fn new(key: &'static str) -> Self {
+        Msg { key, args: Vec::new() }
+    }
+
+    pub fn arg(mut self, name: &'static str, value: impl fmt::Display) -> Self {
+        self.args.push((name, value.to_string()));
+        self
+    }
+
+    pub fn text(&self, lang: Lang) -> String {
+        self.args
+            .iter()
+            .fold(text(lang, self.key).to_string(), |text, (name, value)| {
+                text.replace(&format!("{{{}}}", name), value)
+            })
+    }
+}
+
+// 查找目录中的文本，当前语言缺少该键时使用简体中文，都没有时返回键本身
+pub fn text(lang: Lang, key: &str) -> &str {
+    lookup(lang, key).unwrap_or(key)
+}
+
+// 查找目录中的文本，键不存在时返回 None
+pub fn lookup(lang: Lang, key: &str) -> Option<&'static str> {
+    let catalogs = catalogs();
+    catalogs[lang_index(lang)]
+        .get(key)
+        .or_else(|| catalogs[lang_index(Lang::ZhCn)].get(key))
+        .map(String::as_str)
+}
+
+fn lang_index(lang: Lang) -> usize {
+    match lang {
+        Lang::ZhCn => 0,
+        Lang::En => 1,
+    }
+}
+
+fn catalogs() -> &'static [HashMap<String, String>; 2] {
+    static CATALOGS: OnceLock<[HashMap<String, String>; 2]> = OnceLock::new();
+    CATALOGS.get_or_init(|| {
+        // 启动时 check_catalogs 已确认能解析，这里解析失败时退化为空目录
+        [parse_catalog(ZH_CN_CATALOG).unwrap_or_default(), parse_catalog(EN_CATALOG).unwrap_or_default()]
+    })
+}
+
+fn parse_catalog(source: &str) -> Result<HashMap<String, String>, String> {
+    let table: toml::Table = toml::from_str(source).map_err(|e| e.to_string())?;
+    let mut catalog = HashMap::new();
+    for (section, entries) in table {
+        let entries = entries.as_table().ok_or_else(|| format!("{} 必须是表", section))?;
+        for (key, value) in entries {
+            let value = value.as_str().ok_or_else(|| format!("{}.{} 必须是字符串", section, key))?;
+            catalog.insert(format!("{}.{}", section, key), value.to_string());
+        }
+    }
+    Ok(catalog)
+}
+
+// 启动时校验消息目录：都能解析，且各语言的键完全一致
+pub fn check_catalogs() -> Result<(), String> {
+    let zh = parse_catalog(ZH_CN_CATALOG).map_err(|e| format!("消息目录 zh-CN 解析失败: {}", e))?;
+    let en = parse_catalog(EN_CATALOG).map_err(|e| format!("消息目录 en 解析失败: {}", e))?;
+    let zh_keys: BTreeSet<&String> = zh.keys().collect();
+    let en_keys: BTreeSet<&String> = en.keys().collect();
+    let missing: Vec<String> = zh_keys
+        .symmetric_difference(&en_keys)
+        .map(|key| format!("{} ({})", key, if zh.contains_key(*key) { "en 缺少" } else { "zh-CN 缺少" }))
+        .collect();
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("消息目录的键不一致: {}", missing.join(", ")))
+    }
+}
+
+// 中间件：把 AppError 生成的错误响应替换为请求语言的版本（带上请求中的上游调用记录），并为所有响应加上 Content-Language
+pub async fn localize_errors(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let header_lang = Lang::from_headers(req.headers());
+    let response = next.call(req).await?;
+    let lang = response.request().extensions().get::<Lang>().copied().unwrap_or(header_lang);
+    let attempts = response.request().extensions().get::<AttemptLog>().map(AttemptLog::records).unwrap_or_default();
+
+    let localized = response
+        .response()
+        .error()
+        .and_then(|e| e.as_error::<AppError>())
+        .map(|e| e.to_response(lang, &attempts));
+    let mut response = match localized {
+        Some(localized) => response.into_response(localized).map_into_right_body(),
+        None => response.map_into_left_body(),
+    };
+    response
+        .headers_mut()
+        .insert(CONTENT_LANGUAGE, HeaderValue::from_static(lang.code()));
+    Ok(response)
+}
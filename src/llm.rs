@@ -0,0 +1,821 @@
+use async_trait::async_trait;
+use colored::*;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{cell::Cell, fmt, time::Duration};
+
+use crate::config::{Config, LlmModelConfig};
+use crate::error::AppError;
+use crate::prompt::RenderedPrompt;
+use crate::retry::{parse_retry_after, RetryPolicy, Transient};
+
+// 可用的AI服务类型，对应 Config.llm_provider 和 llm_fallbacks[].provider
+pub const LLM_PROVIDER_NAMES: [&str; 3] = ["dashscope", "openai", "ollama"];
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+}
+
+// 流式输出的增量：回答内容，或开启深度思考时的思考过程
+#[derive(Debug)]
+pub enum StreamDelta {
+    Content(String),
+    Reasoning(String),
+}
+
+// 模型返回的 token 用量
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Usage {
+    #[serde(default)]
+    pub prompt_tokens: u32,
+    #[serde(default)]
+    pub completion_tokens: u32,
+    #[serde(default)]
+    pub total_tokens: u32,
+}
+
+impl Usage {
+    // 累加多次调用（例如结构化模式重新生成）的用量
+    pub fn add(&mut self, other: &Usage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.total_tokens += other.total_tokens;
+    }
+}
+
+// 非流式调用的回复，usage 为服务返回的用量（未返回时为 None）
+#[derive(Debug)]
+pub struct ChatReply {
+    pub content: String,
+    pub usage: Option<Usage>,
+}
+
+// 非流式调用的选项
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChatOptions {
+    pub json: bool,   // 要求模型输出 JSON 对象（各服务的 JSON 模式）
+}
+
+impl ChatMessage {
+    pub fn new(role: &str, content: &str) -> Self {
+        ChatMessage {
+            role: role.to_string(),
+            content: content.to_string(),
+        }
+    }
+}
+
+// 单次调用模型失败的原因，用于判断是否需要重试或换用备用模型
+#[derive(Debug)]
+pub enum LlmError {
+    Network(String),
+    Http { status: u16, reason: String, retry_after: Option<Duration> },   // reason 已截断，完整响应只写入服务端日志
+    InvalidResponse(String),
+    Interrupted(String),   // 已经输出部分内容后流式连接中断，不能再重试或换模型
+}
+
+impl fmt::Display for LlmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LlmError::Network(e) => write!(f, "网络错误: {}", e),
+            LlmError::Http { status, reason, .. } => write!(f, "HTTP {}: {}", status, reason),
+            LlmError::InvalidResponse(e) => write!(f, "{}", e),
+            LlmError::Interrupted(e) => write!(f, "输出中断: {}", e),
+        }
+    }
+}
+
+// 网络错误、限流(429)和服务端错误(5xx)可以重试，也可以换用备用模型
+impl Transient for LlmError {
+    fn is_transient(&self) -> bool {
+        match self {
+            LlmError::Network(_) => true,
+            LlmError::Http { status, .. } => *status == 429 || *status >= 500,
+            LlmError::InvalidResponse(_) | LlmError::Interrupted(_) => false,
+        }
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        match self {
+            LlmError::Http { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for LlmError {
+    fn from(e: reqwest::Error) -> Self {
+        LlmError::Network(e.without_url().to_string())
+    }
+}
+
+// 大模型服务：发送对话消息，返回模型回复
+#[async_trait(?Send)]
+pub trait LlmProvider {
+    fn model(&self) -> &str;
+    async fn chat(&self, messages: &[ChatMessage], options: ChatOptions) -> Result<ChatReply, LlmError>;
+
+    // 流式对话，每收到一段增量调用一次 on_delta，结束后返回用量（服务未返回时为 None）
+    // 不支持流式的服务整段回复作为一个增量输出
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        _thinking: bool,
+        on_delta: &dyn Fn(StreamDelta),
+    ) -> Result<Option<Usage>, LlmError> {
+        let reply = self.chat(messages, ChatOptions::default()).await?;
+        on_delta(StreamDelta::Content(reply.content));
+        Ok(reply.usage)
+    }
+}
+
+// 通义千问 DashScope，使用其 OpenAI 兼容接口，额外传入 enable_thinking 控制深度思考
+pub struct DashScope {
+    inner: OpenAiCompatible,
+}
+
+#[async_trait(?Send)]
+impl LlmProvider for DashScope {
+    fn model(&self) -> &str {
+        &self.inner.model
+    }
+
+    async fn chat(&self, messages: &[ChatMessage], options: ChatOptions) -> Result<ChatReply, LlmError> {
+        let mut body = json!({
+            "model": self.inner.model,
+            "messages": messages,
+            "temperature": 0.7,
+            "enable_thinking": false  // 非流式调用必须设置为false
+        });
+        if options.json {
+            body["response_format"] = json!({ "type": "json_object" });
+        }
+        self.inner.send(&body).await
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        thinking: bool,
+        on_delta: &dyn Fn(StreamDelta),
+    ) -> Result<Option<Usage>, LlmError> {
+        let body = json!({
+            "model": self.inner.model,
+            "messages": messages,
+            "temperature": 0.7,
+            "stream": true,
+            "stream_options": { "include_usage": true },
+            "enable_thinking": thinking  // 开启后思考过程通过 reasoning_content 返回
+        });
+        self.inner.send_stream(&body, on_delta).await
+    }
+}
+
+// OpenAI 兼容接口（OpenAI、vLLM、llama.cpp server 等），POST {base_url}/chat/completions
+pub struct OpenAiCompatible {
+    client: Client,
+    timeout: Duration,
+    base_url: String,
+    api_key: String,
+    model: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChatCompletionResponse {
+    choices: Vec<ChatCompletionChoice>,
+    #[serde(default)]
+    usage: Option<Usage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChatCompletionChoice {
+    message: ChatMessage,
+}
+
+impl OpenAiCompatible {
+    fn request(&self, body: &Value) -> reqwest::RequestBuilder {
+        let url = format!("{}/chat/completions", self.base_url.trim_end_matches('/'));
+        let request = self.client.post(url).timeout(self.timeout).json(body);
+        // 本地服务通常不需要密钥
+        if self.api_key.is_empty() {
+            request
+        } else {
+            request.header("Authorization", format!("Bearer {}", self.api_key))
+        }
+    }
+
+    async fn send(&self, body: &Value) -> Result<ChatReply, LlmError> {
+        let response = send_checked(self.request(body)).await?;
+        let response_body = response.text().await?;
+
+        // 尝试解析响应
+        match serde_json::from_str::<ChatCompletionResponse>(&response_body) {
+            Ok(completion) => {
+                let usage = completion.usage;
+                completion
+                    .choices
+                    .into_iter()
+                    .next()
+                    .map(|choice| ChatReply { content: choice.message.content, usage })
+                    .ok_or_else(|| LlmError::InvalidResponse("AI返回了空回复".to_string()))
+            }
+            Err(e) => {
+                // 尝试解析错误消息
+                if let Ok(error_value) = serde_json::from_str::<Value>(&response_body) {
+                    if let Some(error_msg) = error_value["error"]["message"].as_str() {
+                        return Err(LlmError::InvalidResponse(format!("AI解析失败: {}", truncate_reason(error_msg))));
+                    }
+                }
+                Err(invalid_body("JSON解析失败", &e, &response_body))
+            }
+        }
+    }
+
+    async fn send_stream(&self, body: &Value, on_delta: &dyn Fn(StreamDelta)) -> Result<Option<Usage>, LlmError> {
+        let response = send_checked(self.request(body)).await?;
+        read_stream(response, parse_sse_line, on_delta).await
+    }
+}
+
+#[async_trait(?Send)]
+impl LlmProvider for OpenAiCompatible {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn chat(&self, messages: &[ChatMessage], options: ChatOptions) -> Result<ChatReply, LlmError> {
+        let mut body = json!({
+            "model": self.model,
+            "messages": messages,
+            "temperature": 0.7
+        });
+        if options.json {
+            body["response_format"] = json!({ "type": "json_object" });
+        }
+        self.send(&body).await
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        _thinking: bool,
+        on_delta: &dyn Fn(StreamDelta),
+    ) -> Result<Option<Usage>, LlmError> {
+        let body = json!({
+            "model": self.model,
+            "messages": messages,
+            "temperature": 0.7,
+            "stream": true,
+            "stream_options": { "include_usage": true }
+        });
+        self.send_stream(&body, on_delta).await
+    }
+}
+
+// Ollama 原生接口 POST {base_url}/api/chat
+pub struct Ollama {
+    client: Client,
+    timeout: Duration,
+    base_url: String,
+    model: String,
+}
+
+// prompt_eval_count / eval_count 为提示词和回答的 token 数
+#[derive(Deserialize, Debug)]
+struct OllamaChatResponse {
+    message: ChatMessage,
+    #[serde(default)]
+    prompt_eval_count: Option<u32>,
+    #[serde(default)]
+    eval_count: Option<u32>,
+}
+
+#[async_trait(?Send)]
+impl LlmProvider for Ollama {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn chat(&self, messages: &[ChatMessage], options: ChatOptions) -> Result<ChatReply, LlmError> {
+        let url = format!("{}/api/chat", self.base_url.trim_end_matches('/'));
+        let mut body = json!({
+            "model": self.model,
+            "messages": messages,
+            "stream": false,
+            "options": { "temperature": 0.7 }
+        });
+        if options.json {
+            body["format"] = json!("json");
+        }
+        let response = send_checked(self.client.post(url).timeout(self.timeout).json(&body)).await?;
+        let response_body = response.text().await?;
+        serde_json::from_str::<OllamaChatResponse>(&response_body)
+            .map(|response| ChatReply {
+                content: response.message.content,
+                usage: ollama_usage(response.prompt_eval_count, response.eval_count),
+            })
+            .map_err(|e| invalid_body("JSON解析失败", &e, &response_body))
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        thinking: bool,
+        on_delta: &dyn Fn(StreamDelta),
+    ) -> Result<Option<Usage>, LlmError> {
+        let url = format!("{}/api/chat", self.base_url.trim_end_matches('/'));
+        let mut body = json!({
+            "model": self.model,
+            "messages": messages,
+            "stream": true,
+            "options": { "temperature": 0.7 }
+        });
+        // 不支持思考的模型收到 think 参数会报错，只在开启时传入
+        if thinking {
+            body["think"] = json!(true);
+        }
+        let response = send_checked(self.client.post(url).timeout(self.timeout).json(&body)).await?;
+        read_stream(response, parse_ndjson_line, on_delta).await
+    }
+}
+
+fn ollama_usage(prompt: Option<u32>, completion: Option<u32>) -> Option<Usage> {
+    match (prompt, completion) {
+        (None, None) => None,
+        (prompt, completion) => {
+            let (prompt_tokens, completion_tokens) = (prompt.unwrap_or(0), completion.unwrap_or(0));
+            Some(Usage { prompt_tokens, completion_tokens, total_tokens: prompt_tokens + completion_tokens })
+        }
+    }
+}
+
+// 流式响应中一行的解析函数：输出其中的增量，记录用量，返回 true 表示流已结束
+type LineParser = fn(&str, &mut Option<Usage>, &dyn Fn(StreamDelta)) -> Result<bool, LlmError>;
+
+// 逐行读取流式响应；按字节缓存到换行再解码，避免多字节字符被拆在两个数据块中
+async fn read_stream(
+    mut response: reqwest::Response,
+    parse_line: LineParser,
+    on_delta: &dyn Fn(StreamDelta),
+) -> Result<Option<Usage>, LlmError> {
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut usage = None;
+    while let Some(chunk) = response.chunk().await? {
+        buffer.extend_from_slice(&chunk);
+        while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=pos).collect();
+            if parse_line(&String::from_utf8_lossy(&line), &mut usage, on_delta)? {
+                return Ok(usage);
+            }
+        }
+    }
+    // 最后一行可能没有换行符
+    parse_line(&String::from_utf8_lossy(&buffer), &mut usage, on_delta)?;
+    Ok(usage)
+}
+
+// OpenAI 兼容接口的 SSE：每行 "data: {json}"，以 "data: [DONE]" 结束
+// 开启 include_usage 后最后一个数据块的 choices 为空，只带 usage
+fn parse_sse_line(line: &str, usage: &mut Option<Usage>, on_delta: &dyn Fn(StreamDelta)) -> Result<bool, LlmError> {
+    let Some(data) = line.trim().strip_prefix("data:") else {
+        return Ok(false);
+    };
+    let data = data.trim();
+    if data == "[DONE]" {
+        return Ok(true);
+    }
+
+    let chunk: Value = serde_json::from_str(data).map_err(|e| invalid_body("流式数据解析失败", &e, data))?;
+    if let Some(message) = chunk["error"]["message"].as_str() {
+        return Err(LlmError::InvalidResponse(format!("AI解析失败: {}", truncate_reason(message))));
+    }
+    if !chunk["usage"].is_null() {
+        *usage = serde_json::from_value(chunk["usage"].clone()).ok();
+    }
+    let delta = &chunk["choices"][0]["delta"];
+    if let Some(reasoning) = delta["reasoning_content"].as_str().filter(|s| !s.is_empty()) {
+        on_delta(StreamDelta::Reasoning(reasoning.to_string()));
+    }
+    if let Some(content) = delta["content"].as_str().filter(|s| !s.is_empty()) {
+        on_delta(StreamDelta::Content(content.to_string()));
+    }
+    Ok(false)
+}
+
+// Ollama 的 NDJSON：每行一个 JSON 对象，message.thinking 为思考过程，
+// "done": true 的最后一行带 prompt_eval_count / eval_count；出错时为 {"error": "..."}
+fn parse_ndjson_line(line: &str, usage: &mut Option<Usage>, on_delta: &dyn Fn(StreamDelta)) -> Result<bool, LlmError> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(false);
+    }
+
+    let chunk: Value = serde_json::from_str(line).map_err(|e| invalid_body("流式数据解析失败", &e, line))?;
+    if let Some(message) = chunk["error"].as_str() {
+        return Err(LlmError::InvalidResponse(format!("AI解析失败: {}", truncate_reason(message))));
+    }
+    let message = &chunk["message"];
+    if let Some(thinking) = message["thinking"].as_str().filter(|s| !s.is_empty()) {
+        on_delta(StreamDelta::Reasoning(thinking.to_string()));
+    }
+    if let Some(content) = message["content"].as_str().filter(|s| !s.is_empty()) {
+        on_delta(StreamDelta::Content(content.to_string()));
+    }
+    if chunk["done"].as_bool() == Some(true) {
+        let count = |key: &str| chunk[key].as_u64().map(|n| n as u32);
+        *usage = ollama_usage(count("prompt_eval_count"), count("eval_count"));
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+// 发送请求并检查HTTP状态；上游的错误响应可能包含服务内部信息，完整内容只写入服务端日志，
+// 返回给客户端的 reason 为其中的 error.message（截断）或状态码的标准说明
+async fn send_checked(request: reqwest::RequestBuilder) -> Result<reqwest::Response, LlmError> {
+    let response = request.send().await?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let retry_after = parse_retry_after(response.headers());
+        let body = response.text().await.unwrap_or_default();
+        println!("⚠️  AI服务返回 HTTP {}: {}", status.as_u16(), body);
+        let reason = serde_json::from_str::<Value>(&body)
+            .ok()
+            .and_then(|value| value["error"]["message"].as_str().map(truncate_reason))
+            .unwrap_or_else(|| status.canonical_reason().unwrap_or("unknown").to_string());
+        return Err(LlmError::Http { status: status.as_u16(), reason, retry_after });
+    }
+    Ok(response)
+}
+
+// 返回给客户端的上游错误信息最多保留的字符数
+const MAX_REASON_CHARS: usize = 200;
+
+fn truncate_reason(text: &str) -> String {
+    let text = text.trim();
+    match text.char_indices().nth(MAX_REASON_CHARS) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+// 无法解析的响应：原始内容只写入服务端日志
+fn invalid_body(what: &str, error: &serde_json::Error, body: &str) -> LlmError {
+    println!("⚠️  {}: {} | 原始响应: {}", what, error, body);
+    LlmError::InvalidResponse(format!("{}: {}", what, truncate_reason(&error.to_string())))
+}
+
+// 根据回退链中的一项构建模型服务，base_url 为空时使用各服务的默认地址
+// 共用 AppState 中的 client 及其连接池，超时按该项的 timeout_secs 逐个请求设置
+pub fn provider_from_config(client: &Client, entry: &LlmModelConfig) -> Result<Box<dyn LlmProvider>, LlmError> {
+    let timeout = Duration::from_secs(entry.timeout_secs);
+    let base_url = |default: &str| {
+        if entry.base_url.is_empty() { default.to_string() } else { entry.base_url.clone() }
+    };
+    let openai = |default: &str| OpenAiCompatible {
+        client: client.clone(),
+        timeout,
+        base_url: base_url(default),
+        api_key: entry.api_key.clone(),
+        model: entry.model.clone(),
+    };
+
+    match entry.provider.as_str() {
+        "dashscope" => Ok(Box::new(DashScope {
+            inner: openai("https://dashscope.aliyuncs.com/compatible-mode/v1"),
+        })),
+        "openai" => Ok(Box::new(openai("https://api.openai.com/v1"))),
+        "ollama" => Ok(Box::new(Ollama {
+            client: client.clone(),
+            timeout,
+            base_url: base_url("http://localhost:11434"),
+            model: entry.model.clone(),
+        })),
+        other => Err(LlmError::InvalidResponse(format!("未知的AI服务类型: {}", other))),
+    }
+}
+
+// 回退链中被跳过的模型及原因
+#[derive(Serialize, Debug, Clone)]
+pub struct SkippedModel {
+    pub model: String,
+    pub reason: String,
+}
+
+// 模型回答以及实际给出回答的模型
+#[derive(Debug)]
+pub struct LlmAnswer {
+    pub content: String,
+    pub model: String,
+    pub skipped: Vec<SkippedModel>,
+    pub usage: Option<Usage>,
+}
+
+// 流式回答结束后的汇总，回答内容已经通过增量输出
+#[derive(Debug)]
+pub struct StreamSummary {
+    pub model: String,
+    pub skipped: Vec<SkippedModel>,
+    pub usage: Option<Usage>,
+}
+
+// 按 Config.llm_chain 的顺序调用模型；只有临时故障（重试后仍失败）才换用下一个模型，
+// 密钥无效、请求错误等永久故障直接返回，避免掩盖配置问题
+pub async fn ask_with_fallback(
+    client: &Client,
+    prompt: &RenderedPrompt,
+    config: &Config,
+    retry: &RetryPolicy,
+) -> Result<LlmAnswer, AppError> {
+    chat_with_fallback(client, &prompt.messages(), ChatOptions::default(), config, retry).await
+}
+
+pub async fn chat_with_fallback(
+    client: &Client,
+    messages: &[ChatMessage],
+    options: ChatOptions,
+    config: &Config,
+    retry: &RetryPolicy,
+) -> Result<LlmAnswer, AppError> {
+    let mut skipped: Vec<SkippedModel> = Vec::new();
+    for entry in config.llm_chain() {
+        println!("\n🧠 正在调用AI分析...");
+        println!("🤖 模型: {} ({})", entry.model.green(), entry.provider);
+
+        let result = match provider_from_config(client, &entry) {
+            Ok(provider) => retry.run(provider.model(), || provider.chat(messages, options)).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(reply) => {
+                if let Some(usage) = &reply.usage {
+                    println!("📊 token 用量: 提示词 {}，回答 {}", usage.prompt_tokens, usage.completion_tokens);
+                }
+                return Ok(LlmAnswer {
+                    content: reply.content,
+                    model: entry.model,
+                    skipped,
+                    usage: reply.usage,
+                })
+            }
+            Err(e) => {
+                let transient = e.is_transient();
+                skipped.push(SkippedModel {
+                    model: entry.model,
+                    reason: e.to_string(),
+                });
+                if !transient {
+                    return Err(AppError::Llm(describe(&skipped)));
+                }
+                println!("⚠️  模型调用失败: {}，尝试下一个模型", e);
+            }
+        }
+    }
+    Err(AppError::Llm(describe(&skipped)))
+}
+
+// 流式版本的回退链：尚未输出任何内容时的临时故障会重试或换用下一个模型，
+// 输出开始后连接中断则直接返回错误，避免客户端收到两段拼接的回答
+pub async fn ask_stream_with_fallback(
+    client: &Client,
+    prompt: &RenderedPrompt,
+    config: &Config,
+    retry: &RetryPolicy,
+    thinking: bool,
+    on_delta: &dyn Fn(StreamDelta),
+) -> Result<StreamSummary, AppError> {
+    let messages = prompt.messages();
+    let emitted = Cell::new(false);
+    let forward = |delta: StreamDelta| {
+        emitted.set(true);
+        on_delta(delta);
+    };
+
+    let mut skipped: Vec<SkippedModel> = Vec::new();
+    for entry in config.llm_chain() {
+        println!("\n🧠 正在调用AI分析（流式）...");
+        println!("🤖 模型: {} ({})", entry.model.green(), entry.provider);
+
+        let result = match provider_from_config(client, &entry) {
+            Ok(provider) => stream_provider(provider.as_ref(), &messages, thinking, retry, &emitted, &forward).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(usage) => {
+                return Ok(StreamSummary {
+                    model: entry.model,
+                    skipped,
+                    usage,
+                })
+            }
+            Err(e) => {
+                let transient = e.is_transient();
+                skipped.push(SkippedModel {
+                    model: entry.model,
+                    reason: e.to_string(),
+                });
+                if !transient {
+                    return Err(AppError::Llm(describe(&skipped)));
+                }
+                println!("⚠️  模型调用失败: {}，尝试下一个模型", e);
+            }
+        }
+    }
+    Err(AppError::Llm(describe(&skipped)))
+}
+
+// 调用一个模型的流式接口；emitted 记录是否已经向客户端输出过内容，此后的错误包装为 Interrupted，
+// 既不重试也不换用备用模型
+async fn stream_provider(
+    provider: &dyn LlmProvider,
+    messages: &[ChatMessage],
+    thinking: bool,
+    retry: &RetryPolicy,
+    emitted: &Cell<bool>,
+    forward: &dyn Fn(StreamDelta),
+) -> Result<Option<Usage>, LlmError> {
+    retry
+        .run(provider.model(), || async {
+            provider.chat_stream(messages, thinking, forward).await.map_err(|e| {
+                if emitted.get() { LlmError::Interrupted(e.to_string()) } else { e }
+            })
+        })
+        .await
+}
+
+fn describe(skipped: &[SkippedModel]) -> String {
+    skipped
+        .iter()
+        .map(|s| format!("{}: {}", s.model, s.reason))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RetryConfig;
+    use std::cell::RefCell;
+
+    // 依次解析 lines，返回输出的增量（思考过程带 "~" 前缀）、用量和结束前解析的行数
+    fn parse(parse_line: LineParser, lines: &[&str]) -> (Result<Vec<String>, LlmError>, Option<Usage>, usize) {
+        let deltas = RefCell::new(Vec::new());
+        let on_delta = |delta: StreamDelta| {
+            deltas.borrow_mut().push(match delta {
+                StreamDelta::Content(text) => text,
+                StreamDelta::Reasoning(text) => format!("~{}", text),
+            })
+        };
+        let mut usage = None;
+        for (i, line) in lines.iter().enumerate() {
+            match parse_line(line, &mut usage, &on_delta) {
+                Ok(true) => return (Ok(deltas.into_inner()), usage, i + 1),
+                Ok(false) => {}
+                Err(e) => return (Err(e), usage, i + 1),
+            }
+        }
+        (Ok(deltas.into_inner()), usage, lines.len())
+    }
+
+    #[test]
+    fn parses_sse_deltas_usage_and_done() {
+        let lines = [
+            ": keep-alive\n",
+            "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\",\"content\":\"\"}}]}\n",
+            "data: {\"choices\":[{\"delta\":{\"reasoning_content\":\"想一想\"}}]}\n",
+            "\n",
+            "data:{\"choices\":[{\"delta\":{\"content\":\"**1. 商务\"}}]}\r\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"聚餐**\"}}],\"usage\":null}\n",
+            "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":12,\"completion_tokens\":5,\"total_tokens\":17}}\n",
+            "data: [DONE]\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"不应输出\"}}]}\n",
+        ];
+        let (deltas, usage, parsed) = parse(parse_sse_line, &lines);
+        assert_eq!(deltas.unwrap(), ["~想一想", "**1. 商务", "聚餐**"]);
+        assert_eq!(usage.unwrap().total_tokens, 17);
+        assert_eq!(parsed, 8);
+    }
+
+    #[test]
+    fn sse_error_and_garbage_chunks_fail() {
+        let lines = [
+            "data: {\"choices\":[{\"delta\":{\"content\":\"你好\"}}]}\n",
+            "data: {\"error\":{\"message\":\"Arrearage\",\"code\":\"Arrearage\"}}\n",
+        ];
+        let (result, _, _) = parse(parse_sse_line, &lines);
+        match result {
+            Err(LlmError::InvalidResponse(message)) => assert_eq!(message, "AI解析失败: Arrearage"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let (result, _, _) = parse(parse_sse_line, &["data: <html>502 Bad Gateway</html>\n"]);
+        assert!(matches!(result, Err(LlmError::InvalidResponse(message)) if message.starts_with("流式数据解析失败")));
+    }
+
+    #[test]
+    fn parses_ollama_ndjson() {
+        let lines = [
+            "{\"model\":\"qwen3\",\"message\":{\"role\":\"assistant\",\"content\":\"\",\"thinking\":\"嗯\"},\"done\":false}\n",
+            "{\"model\":\"qwen3\",\"message\":{\"role\":\"assistant\",\"content\":\"推荐\"},\"done\":false}\n",
+            "\n",
+            "{\"model\":\"qwen3\",\"message\":{\"role\":\"assistant\",\"content\":\"海底捞\"},\"done\":false}\n",
+            "{\"model\":\"qwen3\",\"message\":{\"role\":\"assistant\",\"content\":\"\"},\"done\":true,\"prompt_eval_count\":30,\"eval_count\":8}",
+        ];
+        let (deltas, usage, parsed) = parse(parse_ndjson_line, &lines);
+        assert_eq!(deltas.unwrap(), ["~嗯", "推荐", "海底捞"]);
+        let usage = usage.unwrap();
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens, usage.total_tokens), (30, 8, 38));
+        assert_eq!(parsed, 5);
+
+        let (result, _, _) = parse(parse_ndjson_line, &["{\"error\":\"model \\\"qwen9\\\" not found\"}\n"]);
+        match result {
+            Err(LlmError::InvalidResponse(message)) => assert_eq!(message, "AI解析失败: model \"qwen9\" not found"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn truncates_upstream_reasons() {
+        assert_eq!(truncate_reason("  rate limited \n"), "rate limited");
+        let long = "错".repeat(MAX_REASON_CHARS + 10);
+        let reason = truncate_reason(&long);
+        assert_eq!(reason.chars().count(), MAX_REASON_CHARS + 1);
+        assert!(reason.ends_with('…'));
+    }
+
+    // 按顺序输出 deltas 后返回 error 的模型，记录被调用的次数
+    struct FailingStream {
+        deltas: Vec<&'static str>,
+        error: fn() -> LlmError,
+        calls: Cell<u32>,
+    }
+
+    #[async_trait(?Send)]
+    impl LlmProvider for FailingStream {
+        fn model(&self) -> &str {
+            "mock"
+        }
+
+        async fn chat(&self, _messages: &[ChatMessage], _options: ChatOptions) -> Result<ChatReply, LlmError> {
+            unreachable!()
+        }
+
+        async fn chat_stream(
+            &self,
+            _messages: &[ChatMessage],
+            _thinking: bool,
+            on_delta: &dyn Fn(StreamDelta),
+        ) -> Result<Option<Usage>, LlmError> {
+            self.calls.set(self.calls.get() + 1);
+            for delta in &self.deltas {
+                on_delta(StreamDelta::Content(delta.to_string()));
+            }
+            Err((self.error)())
+        }
+    }
+
+    async fn stream(provider: &FailingStream) -> (Result<Option<Usage>, LlmError>, bool) {
+        let config = Config {
+            attempts: 3,
+            retry: RetryConfig { initial_backoff_ms: 1, max_backoff_ms: 1, multiplier: 1.0 },
+            ..Config::default()
+        };
+        let retry = RetryPolicy::from_config(&config);
+        let emitted = Cell::new(false);
+        let forward = |_: StreamDelta| emitted.set(true);
+        let result = stream_provider(provider, &[], false, &retry, &emitted, &forward).await;
+        (result, emitted.get())
+    }
+
+    #[actix_rt::test]
+    async fn interrupted_after_output_is_not_retried() {
+        let provider = FailingStream {
+            deltas: vec!["**1. 商务"],
+            error: || LlmError::Network("connection reset".to_string()),
+            calls: Cell::new(0),
+        };
+        let (result, emitted) = stream(&provider).await;
+        assert!(emitted);
+        assert_eq!(provider.calls.get(), 1);
+        let error = result.unwrap_err();
+        assert!(!error.is_transient());
+        assert_eq!(error.to_string(), "输出中断: 网络错误: connection reset");
+    }
+
+    #[actix_rt::test]
+    async fn failure_before_output_is_retried() {
+        let provider = FailingStream {
+            deltas: Vec::new(),
+            error: || LlmError::Http { status: 503, reason: "Service Unavailable".to_string(), retry_after: None },
+            calls: Cell::new(0),
+        };
+        let (result, emitted) = stream(&provider).await;
+        assert!(!emitted);
+        assert_eq!(provider.calls.get(), 3);
+        let error = result.unwrap_err();
+        assert!(error.is_transient(), "{}", error);
+    }
+}
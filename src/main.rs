@@ -1,372 +1,420 @@
 use serde::{Deserialize, Serialize};
 use reqwest::Client;
-use std::{error::Error, fs, time::Duration, process};
+use std::{time::Duration, process};
 use chrono::Local;
 use serde_json::{Value, json};
 use colored::*;
-use actix_web::{web, App, HttpResponse, HttpServer, middleware, get, post};
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer, Responder, middleware, get, post, http::KeepAlive};
+use actix_web_lab::sse::{self, Sse};
 use actix_cors::Cors;
 use std::sync::Arc;
+use tokio::sync::mpsc;
+
+mod amap;
+mod cli;
+mod config;
+mod error;
+mod filter;
+mod geocoding;
+mod hours;
+mod i18n;
+mod llm;
+mod poi_types;
+mod prompt;
+mod ranking;
+mod recommendation;
+mod reload;
+mod retry;
+mod search;
+
+use clap::Parser;
+use amap::{Extensions, PoiSearchResponse, poi_detail, search_text};
+use cli::{Cli, Command};
+use config::{Config, ConfigStore, ValidationReport, AMAP_MAX_PAGE, AMAP_MAX_PAGE_SIZE, AMAP_MAX_RADIUS, config_path, load_config};
+use error::AppError;
+use filter::PoiFilter;
+use geocoding::{LocationQuery, geocoder_from_config, resolve_location};
+use i18n::{Lang, Msg, check_catalogs, localize_errors};
+use llm::{StreamDelta, ask_stream_with_fallback, ask_with_fallback};
+use prompt::{PromptContext, PromptTemplates};
+use ranking::{SORT_BY_NAMES, SortBy};
+use recommendation::{ask_structured, render};
+use reload::{reload_and_log, spawn_file_watcher, spawn_sighup_handler};
+use retry::RetryPolicy;
+use search::{FoodSearch, SearchOptions, search_nearby};
+
+
+fn format_food_results(data: &PoiSearchResponse) -> String {
+    let mut result = String::new();
 
+    if data.pois.is_empty() {
+        return "🔍 附近未找到美食场所".to_string();
+    }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct Config {
-    username: String,
-    attempts: u32,
-    keywords: String,
-    city: String,
-    api_key: String,
-    output_file: String,
-    food_radius: u32,
-    food_types: String,
-    max_food_results: u32,
-    qwen_api_key: String,
-    qwen_model: String,
-}
+    result.push_str(&format!("\n🍴 找到 {} 家美食场所:\n", data.pois.len().to_string().green()));
 
-impl Default for Config {
-    fn default() -> Self {
-        Config {
-            username: "default_user".to_string(),
-            attempts: 3,
-            keywords: "大连理工大学开发区校区".to_string(),
-            city: "大连".to_string(),
-            api_key: "1a3d892d650f273ea24b3e2be9beea00".to_string(),
-            output_file: "response_log.json".to_string(),
-            food_radius: 1000,
-            food_types: "050000".to_string(),
-            max_food_results: 5,
-            qwen_api_key: "sk-f05ef9cd88fd436ea4be2b2e3edae7f4".to_string(),
-            qwen_model: "qwen3-235b-a22b".to_string(),
-        }
-    }
-}
+    for (i, poi) in data.pois.iter().enumerate() {
+        let address = poi.address.as_deref().unwrap_or("未知地址");
+        let distance = poi.distance.map(|d| format!("{}米", d)).unwrap_or_else(|| "未知距离".to_string());
 
-fn load_config() -> Result<Config, Box<dyn Error>> {
-    let config_path = "config.json";
-    match fs::read_to_string(config_path) {
-        Ok(contents) => {
-            let config: Config = serde_json::from_str(&contents)?;
-            Ok(config)
-        }
-        Err(_) => {
-            println!("⚠️  配置文件未找到，创建默认配置");
-            let default_config = Config::default();
-            fs::write(
-                config_path,
-                serde_json::to_string_pretty(&default_config)?
-            )?;
-            println!("✅  已创建默认配置文件: {}", config_path);
-            Ok(default_config)
+        result.push_str(&format!("\n{}. {}", (i + 1).to_string().cyan().bold(), poi.name.bold()));
+        result.push_str(&format!("\n   📍 地址: {}", address));
+        result.push_str(&format!("\n   📏 距离: {}", distance));
+        let category = poi_types::lookup(&poi.typecode)
+            .map(|t| t.path(Lang::ZhCn))
+            .unwrap_or_else(|| "未知类型".to_string());
+        result.push_str(&format!("\n   🏷️ 类型: {} ({})", category, poi.typecode));
+
+        if let Some(tel) = &poi.tel {
+            result.push_str(&format!("\n   📞 电话: {}", tel.blue()));
+        }
+        if let Some(open_time) = &poi.biz_ext.open_time {
+            result.push_str(&format!("\n   🕐 营业时间: {}", open_time));
         }
     }
-}
-
-async fn get_location(client: &Client, config: &Config) -> Result<(f64, f64), Box<dyn Error>> {
-    let mut url = reqwest::Url::parse("https://restapi.amap.com/v3/assistant/inputtips")?;
-    url.query_pairs_mut()
-        .append_pair("key", &config.api_key)
-        .append_pair("keywords", &config.keywords)
-        .append_pair("city", &config.city);
-
-    println!("🔍 查询地点坐标: {}", config.keywords.green());
 
-    let response = client.get(url.clone())
-        .header("User-Agent", &format!("{}-geo-service", config.username))
-        .send()
-        .await?;
+    result
+}
 
-    if !response.status().is_success() {
-        return Err(format!("定位API失败: {}", response.status()).into());
-    }
+// API请求结构体定义
+#[derive(Deserialize)]
+struct LocationRequest {
+    #[serde(default)]
+    location: String,
+    city: Option<String>,           // 覆盖 Config.city，限定地理编码范围
+    candidate_id: Option<String>,   // 来自 /api/geocode 的候选ID
+    longitude: Option<f64>,         // 直接指定坐标时跳过地理编码
+    latitude: Option<f64>,
+    radius: Option<u32>,            // 覆盖 Config.food_radius
+    types: Option<String>,          // 覆盖 Config.food_types；与 keyword 一起使用时可以为空字符串，表示不限类型
+    keyword: Option<String>,        // 周边搜索的关键词，如 "兰州拉面"，覆盖 Config.food_keywords
+    #[serde(default)]
+    cuisines: Vec<String>,          // 按名称筛选菜系，如 ["火锅", "日料"]，不能与 types 同时使用
+    #[serde(default)]
+    exclude: Vec<String>,           // 按名称排除的类别，如 ["咖啡厅"]
+    limit: Option<u32>,             // 覆盖 Config.max_food_results
+    page_size: Option<u32>,         // 每页条数，与 limit 含义相同，不能同时使用
+    page: Option<u32>,              // 起始页，从 1 开始
+    pages: Option<u32>,             // 从 page 开始合并的页数，不超过 Config.max_merge_pages
+    sort_by: Option<String>,        // 排序方式，覆盖 Config.ranking.default_sort_by
+    extensions: Option<String>,     // "base" 或 "all"，覆盖 Config.food_extensions
+    open_now: Option<bool>,         // 只返回当前营业（或营业时间未知）的餐厅
+    open_at: Option<String>,        // 只返回该时刻营业的餐厅，如 "2024-05-01 19:30" 或 "19:30"，不能与 open_now: true 同时使用
+    thinking: Option<bool>,         // 流式接口是否输出模型的思考过程
+    structured: Option<bool>,       // AI推荐接口是否返回结构化推荐
+    template: Option<String>,       // 提示词模板名，覆盖 Config.default_template
+    persona: Option<String>,        // 用户画像，填入提示词模板
+    lang: Option<String>,           // 响应语言，覆盖 Accept-Language
+}
 
-    let body = response.text().await?;
-    let data: Value = serde_json::from_str(&body)?;
+impl LocationRequest {
+    // 用请求参数覆盖配置副本，所有无效参数一次性返回
+    fn apply_overrides(&self, config: &mut Config) -> Result<(), ValidationReport> {
+        let mut report = ValidationReport::default();
 
-    if let Some(tips) = data["tips"].as_array() {
-        if tips.is_empty() {
-            return Err("未找到相关地点".into());
+        if let Some(city) = &self.city {
+            if city.trim().is_empty() {
+                report.push_msg("city", Msg::new("validation.required"));
+            } else {
+                config.city = city.trim().to_string();
+            }
         }
-
-        // 尝试获取第一个有效位置
-        for tip in tips {
-            if let Some(location) = tip["location"].as_str() {
-                let coords: Vec<&str> = location.split(',').collect();
-                if coords.len() == 2 {
-                    let longitude = coords[0].parse::<f64>()?;
-                    let latitude = coords[1].parse::<f64>()?;
-                    println!("✅ 坐标解析成功: {:.6}, {:.6}", longitude, latitude);
-                    return Ok((longitude, latitude));
+        if let Some(longitude) = self.longitude {
+            if !(-180.0..=180.0).contains(&longitude) {
+                report.push_msg("longitude", Msg::new("validation.longitude_range").arg("value", longitude));
+            }
+        }
+        if let Some(latitude) = self.latitude {
+            if !(-90.0..=90.0).contains(&latitude) {
+                report.push_msg("latitude", Msg::new("validation.latitude_range").arg("value", latitude));
+            }
+        }
+        if self.longitude.is_some() != self.latitude.is_some() {
+            report.push_msg("longitude/latitude", Msg::new("validation.coordinates_pair"));
+        }
+        if let Some(radius) = self.radius {
+            if (1..=AMAP_MAX_RADIUS).contains(&radius) {
+                config.food_radius = radius;
+            } else {
+                report.push_msg("radius", Msg::new("validation.radius_range").arg("max", AMAP_MAX_RADIUS).arg("value", radius));
+            }
+        }
+        if let Some(keyword) = &self.keyword {
+            match check_keyword(keyword) {
+                Ok(keyword) => config.food_keywords = keyword,
+                Err(msg) => report.push_msg("keyword", msg),
+            }
+        }
+        if let Some(types) = &self.types {
+            if types.trim().is_empty() {
+                // 只按关键词搜索，关键词来自请求的 keyword 或 Config.food_keywords；keyword 无效时上面已报告
+                if !config.food_keywords.is_empty() {
+                    config.food_types = String::new();
+                } else if self.keyword.is_none() {
+                    report.push_msg("keyword", Msg::new("validation.required"));
+                }
+            } else {
+                match poi_types::resolve_type_codes(types) {
+                    Ok(codes) => config.food_types = codes,
+                    Err(invalid) => report.push_msg("types", Msg::new("validation.type_codes").arg("value", invalid.join("|"))),
                 }
             }
         }
-    }
-
-    Err("无法解析坐标，请检查API响应结构".into())
-}
+        if self.types.is_some() && !self.cuisines.is_empty() {
+            report.push_msg("cuisines", Msg::new("validation.cuisines_with_types"));
+        }
+        for (field, terms) in [("cuisines", &self.cuisines), ("exclude", &self.exclude)] {
+            if terms.len() > MAX_FILTER_TERMS {
+                report.push_msg(field, Msg::new("validation.too_many_terms").arg("max", MAX_FILTER_TERMS).arg("value", terms.len()));
+            }
+            if terms.iter().any(|term| term.trim().is_empty()) {
+                report.push_msg(field, Msg::new("validation.empty_term"));
+            }
+        }
+        if self.limit.is_some() && self.page_size.is_some() {
+            report.push_msg("page_size", Msg::new("validation.page_size_with_limit"));
+        }
+        for (field, value) in [("limit", self.limit), ("page_size", self.page_size)] {
+            let Some(limit) = value else { continue };
+            if (1..=AMAP_MAX_PAGE_SIZE).contains(&limit) {
+                config.max_food_results = limit;
+            } else {
+                report.push_msg(field, Msg::new("validation.limit_range").arg("max", AMAP_MAX_PAGE_SIZE).arg("value", limit));
+            }
+        }
+        if let Some(page) = self.page {
+            if !(1..=AMAP_MAX_PAGE).contains(&page) {
+                report.push_msg("page", Msg::new("validation.page_range").arg("max", AMAP_MAX_PAGE).arg("value", page));
+            }
+        }
+        if let Some(pages) = self.pages {
+            if !(1..=config.max_merge_pages).contains(&pages) {
+                report.push_msg("pages", Msg::new("validation.pages_range").arg("max", config.max_merge_pages).arg("value", pages));
+            }
+        }
+        if let Some(extensions) = &self.extensions {
+            match Extensions::parse(extensions) {
+                Some(parsed) => config.food_extensions = parsed.as_str().to_string(),
+                None => report.push_msg("extensions", Msg::new("validation.extensions").arg("value", extensions)),
+            }
+        }
+        if let Some(open_at) = &self.open_at {
+            // open_now: false 只是不按当前时刻筛选，与 open_at 不冲突
+            if self.open_now == Some(true) {
+                report.push_msg("open_at", Msg::new("validation.open_at_with_open_now"));
+            }
+            if hours::parse_open_at(open_at, hours::now(&config.timezone)).is_none() {
+                report.push_msg("open_at", Msg::new("validation.open_at").arg("value", open_at));
+            }
+        }
+        if let Some(sort_by) = &self.sort_by {
+            match SortBy::parse(sort_by) {
+                Some(parsed) => config.ranking.default_sort_by = parsed.name().to_string(),
+                None => report.push_msg(
+                    "sort_by",
+                    Msg::new("validation.unknown_sort_by").arg("value", sort_by).arg("available", format!("{:?}", SORT_BY_NAMES)),
+                ),
+            }
+        }
 
-async fn search_food(client: &Client, config: &Config, location: (f64, f64)) -> Result<Value, Box<dyn Error>> {
-    let (longitude, latitude) = location;
-    let location_str = format!("{},{}", longitude, latitude);
-
-    let mut url = reqwest::Url::parse("https://restapi.amap.com/v3/place/around")?;
-    url.query_pairs_mut()
-        .append_pair("key", &config.api_key)
-        .append_pair("location", &location_str)
-        .append_pair("types", &config.food_types)
-        .append_pair("radius", &config.food_radius.to_string())
-        .append_pair("offset", &config.max_food_results.to_string())
-        .append_pair("extensions", "base");
-
-    println!("\n🍽️  正在搜索附近美食...");
-    println!("📍 中心位置: {}", config.keywords.green());
-    println!("🗺️ 坐标: {:.6}, {:.6}", longitude, latitude);
-    println!("🔍 参数: 半径{}米 | 类型: {} | 最大结果: {}",
-             config.food_radius, config.food_types, config.max_food_results);
-
-    let response = client.get(url)
-        .header("User-Agent", &format!("{}-food-service", config.username))
-        .send()
-        .await?;
-
-    if !response.status().is_success() {
-        return Err(format!("美食搜索API失败: {}", response.status()).into());
+        if report.is_ok() {
+            Ok(())
+        } else {
+            Err(report)
+        }
     }
 
-    let body = response.text().await?;
-    let data: Value = serde_json::from_str(&body)?;
-    Ok(data)
-}
+    // 筛选、分页和排序选项，参数已在 apply_overrides 中校验并写入配置副本；open_at 按配置的时区解释
+    fn search_options(&self, config: &Config) -> SearchOptions {
+        let now = hours::now(&config.timezone);
+        let open_at = match &self.open_at {
+            Some(open_at) => hours::parse_open_at(open_at, now),
+            None => self.open_now.unwrap_or(false).then_some(now),
+        };
+        SearchOptions {
+            filter: PoiFilter::new(&self.cuisines, &self.exclude).with_open_at(open_at),
+            page: self.page.unwrap_or(1),
+            pages: self.pages.unwrap_or(1),
+            sort_by: SortBy::parse(&config.ranking.default_sort_by).unwrap_or_default(),
+            extensions: Extensions::parse(&config.food_extensions).unwrap_or_default(),
+        }
+    }
 
-fn format_food_results(data: &Value) -> String {
-    let mut result = String::new();
+    // 确定使用的提示词模板，在调用高德之前校验，避免无效请求消耗配额
+    fn prompt_template(&self, config: &Config, prompts: &PromptTemplates) -> Result<String, ValidationReport> {
+        let mut report = ValidationReport::default();
+        let name = self.template.as_deref().unwrap_or(&config.default_template).trim();
+        if !prompts.contains(name) {
+            report.push_msg(
+                "template",
+                Msg::new("validation.unknown_template").arg("name", name).arg("available", format!("{:?}", prompts.names())),
+            );
+        }
+        if let Some(persona) = &self.persona {
+            if persona.chars().count() > MAX_PERSONA_CHARS {
+                report.push_msg("persona", Msg::new("validation.persona_too_long").arg("max", MAX_PERSONA_CHARS));
+            }
+        }
 
-    if let Some(pois) = data["pois"].as_array() {
-        if pois.is_empty() {
-            return "🔍 附近未找到美食场所".to_string();
+        if report.is_ok() {
+            Ok(name.to_string())
+        } else {
+            Err(report)
         }
+    }
+}
 
-        result.push_str(&format!("\n🍴 找到 {} 家美食场所:\n", pois.len().to_string().green()));
+// 用户画像会原样写入提示词，限制长度
+const MAX_PERSONA_CHARS: usize = 200;
 
-        for (i, poi) in pois.iter().enumerate() {
-            let name = poi["name"].as_str().unwrap_or("未知名称");
-            let address = poi["address"].as_str().unwrap_or("未知地址");
-            let distance = poi["distance"].as_str().unwrap_or("未知距离");
-            let typecode = poi["typecode"].as_str().unwrap_or("未知类型");
+// cuisines / exclude 的最大条数
+const MAX_FILTER_TERMS: usize = 10;
 
-            result.push_str(&format!("\n{}. {}", (i + 1).to_string().cyan().bold(), name.bold()));
-            result.push_str(&format!("\n   📍 地址: {}", address));
-            result.push_str(&format!("\n   📏 距离: {}米", distance));
-            result.push_str(&format!("\n   🏷️ 类型: {}", typecode));
+// 搜索关键词的最大长度
+const MAX_KEYWORD_CHARS: usize = 50;
 
-            if let Some(tel) = poi["tel"].as_str() {
-                if !tel.is_empty() {
-                    result.push_str(&format!("\n   📞 电话: {}", tel.blue()));
-                }
-            }
-        }
+fn check_keyword(keyword: &str) -> Result<String, Msg> {
+    let keyword = keyword.trim();
+    if keyword.is_empty() {
+        Err(Msg::new("validation.required"))
+    } else if keyword.chars().count() > MAX_KEYWORD_CHARS {
+        Err(Msg::new("validation.keyword_too_long").arg("max", MAX_KEYWORD_CHARS))
     } else {
-        result.push_str("⚠️  未找到有效美食数据");
+        Ok(keyword.to_string())
     }
-
-    result
 }
 
-#[derive(Serialize, Debug)]
-struct QwenRequest {
-    model: String,
-    messages: Vec<QwenMessage>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    temperature: Option<f32>,
-    enable_thinking: bool,  // 添加通义千问要求的特殊参数
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct QwenMessage {
-    role: String,
-    content: String,
+#[derive(Deserialize)]
+struct GeocodeRequest {
+    location: String,
+    city: Option<String>,
+    lang: Option<String>,
 }
 
-#[derive(Deserialize, Debug)]
-struct QwenResponse {
-    choices: Vec<QwenChoice>,
+impl GeocodeRequest {
+    // 地点不能为空；city 省略时使用配置中的默认城市，显式传入时同样不能为空
+    fn query(&self, default_city: &str) -> Result<(String, String), ValidationReport> {
+        let mut report = ValidationReport::default();
+        let location = self.location.trim();
+        if location.is_empty() {
+            report.push_msg("location", Msg::new("validation.required"));
+        }
+        let city = match &self.city {
+            Some(city) if city.trim().is_empty() => {
+                report.push_msg("city", Msg::new("validation.required"));
+                ""
+            }
+            Some(city) => city.trim(),
+            None => default_city,
+        };
+        if report.is_ok() {
+            Ok((location.to_string(), city.to_string()))
+        } else {
+            Err(report)
+        }
+    }
 }
 
-#[derive(Deserialize, Debug)]
-struct QwenChoice {
-    message: QwenMessage,
+// 城市范围内的关键词搜索请求
+#[derive(Deserialize)]
+struct SearchRequest {
+    #[serde(default)]
+    keyword: String,
+    city: Option<String>,           // 覆盖 Config.city
+    types: Option<String>,          // 覆盖 Config.food_types，空字符串表示不限类型
+    page_size: Option<u32>,         // 覆盖 Config.max_food_results
+    page: Option<u32>,              // 从 1 开始
+    extensions: Option<String>,     // "base" 或 "all"，覆盖 Config.food_extensions
+    lang: Option<String>,
 }
 
-async fn ask_qwen(prompt: &str, config: &Config) -> Result<String, Box<dyn Error>> {
-    let client = Client::new();
-    let url = "https://dashscope.aliyuncs.com/compatible-mode/v1/chat/completions";
-
-    // 构建符合通义千问API要求的请求
-    let request = QwenRequest {
-        model: config.qwen_model.clone(),
-        messages: vec![
-            QwenMessage {
-                role: "system".to_string(),
-                content: "你是一个专业的美食评论家，擅长根据用户提供的地点信息给出专业、简洁的美食推荐。".to_string(),
-            },
-            QwenMessage {
-                role: "user".to_string(),
-                content: prompt.to_string(),
-            }
-        ],
-        temperature: Some(0.7),
-        enable_thinking: false,  // 非流式调用必须设置为false
-    };
-
-    println!("\n🧠 正在调用通义千问AI分析...");
-    println!("🤖 模型: {}", config.qwen_model.green());
-
-    let response = client.post(url)
-        .header("Authorization", format!("Bearer {}", config.qwen_api_key))
-        .header("Content-Type", "application/json")
-        .json(&request)
-        .send()
-        .await?;
+impl SearchRequest {
+    // 用请求参数覆盖配置副本，所有无效参数一次性返回
+    fn apply_overrides(&self, config: &mut Config) -> Result<(), ValidationReport> {
+        let mut report = ValidationReport::default();
 
-    if !response.status().is_success() {
-        let status = response.status();
-        let body = response.text().await?;
-        return Err(format!("AI调用失败 ({}): {}", status, body).into());
-    }
-
-    let response_body = response.text().await?;
-    println!("🔍 AI原始响应: {}", response_body);  // 调试输出
-
-    // 尝试解析响应
-    match serde_json::from_str::<QwenResponse>(&response_body) {
-        Ok(qwen_response) => {
-            if let Some(first_choice) = qwen_response.choices.first() {
-                Ok(first_choice.message.content.clone())
+        match check_keyword(&self.keyword) {
+            Ok(keyword) => config.food_keywords = keyword,
+            Err(msg) => report.push_msg("keyword", msg),
+        }
+        if let Some(city) = &self.city {
+            if city.trim().is_empty() {
+                report.push_msg("city", Msg::new("validation.required"));
             } else {
-                Err("AI返回了空回复".into())
+                config.city = city.trim().to_string();
             }
         }
-        Err(e) => {
-            // 尝试解析错误消息
-            if let Ok(error_value) = serde_json::from_str::<Value>(&response_body) {
-                if let Some(error_msg) = error_value["error"]["message"].as_str() {
-                    return Err(format!("AI解析失败: {}", error_msg).into());
+        if let Some(types) = &self.types {
+            if types.trim().is_empty() {
+                config.food_types = String::new();
+            } else {
+                match poi_types::resolve_type_codes(types) {
+                    Ok(codes) => config.food_types = codes,
+                    Err(invalid) => report.push_msg("types", Msg::new("validation.type_codes").arg("value", invalid.join("|"))),
                 }
             }
-            Err(format!("JSON解析失败: {} | 原始响应: {}", e, response_body).into())
         }
-    }
-}
-
-fn generate_ai_prompt(food_data: &Value, location: &str) -> String {
-    let mut prompt = String::new();
-
-    // 添加角色设定和任务描述
-    prompt.push_str("你是一位专业的美食推荐顾问，擅长根据地理位置和餐厅信息为用户提供个性化的餐饮建议。\n\n");
-    
-    prompt.push_str(&format!(
-        "📍 用户位置：{}\n",
-        location
-    ));
-    
-    // 从food_data中提取搜索半径
-    let radius = if let Some(radius) = food_data["radius"].as_str() {
-        radius
-    } else {
-        "1000" // 默认值
-    };
-    
-    prompt.push_str(&format!(
-        "🔍 搜索范围：半径{}米\n\n",
-        radius
-    ));
-
-    // 添加餐厅类型代码说明
-    prompt.push_str("📋 餐厅类型说明：\n");
-    prompt.push_str("• 050100: 中餐厅/综合餐厅\n");
-    prompt.push_str("• 050200: 外国餐厅\n");
-    prompt.push_str("• 050300: 快餐厅\n");
-    prompt.push_str("• 050400: 休闲餐饮场所\n");
-    prompt.push_str("• 050500: 咖啡厅\n\n");
-
-    if let Some(pois) = food_data["pois"].as_array() {
-        prompt.push_str("🍽️ 附近美食场所详情：\n");
-
-        for (i, poi) in pois.iter().enumerate().take(8) { // 增加到8个餐厅
-            let name = poi["name"].as_str().unwrap_or("未知餐厅");
-            let address = poi["address"].as_str().unwrap_or("未知地址");
-            let distance = poi["distance"].as_str().unwrap_or("未知距离");
-            let typecode = poi["typecode"].as_str().unwrap_or("未知类型");
-            
-            // 根据类型代码添加餐厅类型描述
-            let type_desc = match typecode {
-                "050100" => "中餐厅/综合餐厅",
-                "050200" => "外国餐厅",
-                "050300" => "快餐厅",
-                "050400" => "休闲餐饮",
-                "050500" => "咖啡厅",
-                _ => "其他餐饮"
-            };
+        if let Some(page_size) = self.page_size {
+            if (1..=AMAP_MAX_PAGE_SIZE).contains(&page_size) {
+                config.max_food_results = page_size;
+            } else {
+                report.push_msg("page_size", Msg::new("validation.limit_range").arg("max", AMAP_MAX_PAGE_SIZE).arg("value", page_size));
+            }
+        }
+        if let Some(page) = self.page {
+            if !(1..=AMAP_MAX_PAGE).contains(&page) {
+                report.push_msg("page", Msg::new("validation.page_range").arg("max", AMAP_MAX_PAGE).arg("value", page));
+            }
+        }
+        if let Some(extensions) = &self.extensions {
+            match Extensions::parse(extensions) {
+                Some(parsed) => config.food_extensions = parsed.as_str().to_string(),
+                None => report.push_msg("extensions", Msg::new("validation.extensions").arg("value", extensions)),
+            }
+        }
 
-            prompt.push_str(&format!(
-                "{}. 【{}】{}\n   📍 地址：{}\n   🚶 距离：{}米\n   🏷️ 类型：{} ({})\n\n",
-                i + 1, type_desc, name, address, distance, typecode, type_desc
-            ));
+        if report.is_ok() {
+            Ok(())
+        } else {
+            Err(report)
         }
     }
-
-    prompt.push_str("🎯 请基于以上信息提供专业分析和推荐：\n\n");
-    prompt.push_str("**1. 商务聚餐推荐** (1-3家)\n");
-    prompt.push_str("   - 选择标准：环境优雅、服务专业、适合商务交流\n");
-    prompt.push_str("   - 请说明推荐理由和特色\n\n");
-    
-    prompt.push_str("**2. 学生经济餐厅推荐** (1-2家)\n");
-    prompt.push_str("   - 选择标准：价格实惠、分量足够、营养均衡\n");
-    prompt.push_str("   - 请说明性价比优势\n\n");
-    
-    prompt.push_str("**3. 地理位置分析**\n");
-    prompt.push_str("   - 分析各餐厅的交通便利性\n");
-    prompt.push_str("   - 评估距离用户位置的合理性\n");
-    prompt.push_str("   - 考虑周边环境和配套设施\n\n");
-    
-    prompt.push_str("**4. 综合评价与建议** (100字以内)\n");
-    prompt.push_str("   - 总结该区域餐饮特色\n");
-    prompt.push_str("   - 给出最佳用餐时段建议\n\n");
-    
-    prompt.push_str("📝 **输出要求：**\n");
-    prompt.push_str("- 使用清晰的结构化格式\n");
-    prompt.push_str("- 语言专业但易懂，避免使用emoji表情\n");
-    prompt.push_str("- 每个推荐都要有具体理由\n");
-    prompt.push_str("- 考虑不同用户群体的需求差异\n");
-    prompt.push_str("- 如果信息不足，请诚实说明并给出替代建议");
-
-    prompt
-}
-
-// API请求结构体定义
-#[derive(Deserialize)]
-struct LocationRequest {
-    location: String,
-    /*city: Option<String>,*/
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-struct ApiRequest {
-    message: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 struct MapRequest {
+    #[serde(default)]
     location: String,
+    candidate_id: Option<String>,
+    longitude: Option<f64>,
+    latitude: Option<f64>,
     zoom: Option<u8>,
     size: Option<String>,
     markers: Option<Vec<String>>,
+    lang: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LangQuery {
+    lang: Option<String>,
+}
+
+// 高德 POI id 由字母和数字组成，如 "B0JDMS5KTX"
+const MAX_POI_ID_LEN: usize = 32;
+
+#[derive(Deserialize)]
+struct PoiTypesQuery {
+    #[serde(default)]
+    q: String,                      // 按编码前缀或名称筛选，为空时返回全部
+    lang: Option<String>,
 }
 
 // API响应结构体定义
 #[derive(Serialize)]
 struct ApiResponse {
     success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<String>,   // 失败时的机器可读错误码，见 AppError::code
     message: String,
     data: Option<Value>,
     error: Option<String>,
@@ -382,17 +430,66 @@ struct MapResponse {
     timestamp: String,
 }
 
+// 根据请求字段决定地点的指定方式：坐标 > 候选ID > 文本
+fn location_query<'a>(
+    location: &'a str,
+    candidate_id: &'a Option<String>,
+    longitude: Option<f64>,
+    latitude: Option<f64>,
+) -> Result<LocationQuery<'a>, AppError> {
+    match (longitude, latitude) {
+        (Some(longitude), Some(latitude)) => Ok(LocationQuery::Coordinates { longitude, latitude, name: location }),
+        (None, None) => match candidate_id.as_deref() {
+            Some(id) if !id.is_empty() => Ok(LocationQuery::CandidateId(id)),
+            _ => Ok(LocationQuery::Text(location)),
+        },
+        _ => Err(AppError::invalid_msg("longitude/latitude", Msg::new("validation.coordinates_pair"))),
+    }
+}
+
+// 地理编码接口 - 返回所有候选地点
+#[post("/api/geocode")]
+async fn geocode_api(
+    app_data: web::Data<AppState>,
+    req: web::Json<GeocodeRequest>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse, AppError> {
+    let lang = Lang::from_request(&http_req, req.lang.as_deref())?;
+    let config = app_data.config.current();
+    let (location, city) = req.query(&config.city)?;
+
+    let retry = RetryPolicy::for_request(&config, &http_req);
+    let geocoder = geocoder_from_config(&app_data.client, &config, &retry);
+    let candidates = geocoder.resolve(&location, &city).await.map_err(AppError::Geocoding)?;
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        code: None,
+        message: Msg::new("message.geocode").arg("count", candidates.len()).text(lang),
+        data: Some(json!({
+            "query": location,
+            "city": city,
+            "candidates": candidates,
+            "attempts": retry.records()
+        })),
+        error: None,
+    }))
+}
+
 // 位置信息和美食数据接口
 #[post("/api/location-food")]
 async fn location_food_api(
     app_data: web::Data<AppState>,
     req: web::Json<LocationRequest>,
-) -> Result<HttpResponse, actix_web::Error> {
-    let config = app_data.config.clone();
+    http_req: HttpRequest,
+) -> Result<HttpResponse, AppError> {
+    let lang = Lang::from_request(&http_req, req.lang.as_deref())?;
+    let config = app_data.config.current();
     let client = app_data.client.clone();
     
     // 创建一个可修改的配置副本
     let mut config_clone = (*config).clone();
+    req.apply_overrides(&mut config_clone)?;
+    let retry = RetryPolicy::for_request(&config_clone, &http_req);
     
     // 使用请求中的位置信息
     if !req.location.is_empty() {
@@ -401,50 +498,46 @@ async fn location_food_api(
     }
     
     // 获取地点坐标
-    let location = match get_location(&client, &config_clone).await {
-        Ok(loc) => loc,
-        Err(e) => {
-            return Ok(HttpResponse::BadRequest().json(ApiResponse {
-                success: false,
-                message: "Failed to get location coordinates".to_string(),
-                data: None,
-                error: Some(e.to_string()),
-            }));
-        }
-    };
+    let query = location_query(&config_clone.keywords, &req.candidate_id, req.longitude, req.latitude)?;
+    let candidate = resolve_location(&client, &config_clone, &retry, query).await?;
+    let location = (candidate.longitude, candidate.latitude);
+    if req.location.is_empty() {
+        config_clone.keywords = candidate.name.clone();
+    }
     
     // 搜索附近美食
-    let food_data = match search_food(&client, &config_clone, location).await {
-        Ok(data) => data,
-        Err(e) => {
-            return Ok(HttpResponse::InternalServerError().json(ApiResponse {
-                success: false,
-                message: "Failed to search for food".to_string(),
-                data: None,
-                error: Some(e.to_string()),
-            }));
-        }
-    };
+    let options = req.search_options(&config_clone);
+    let FoodSearch { mut food_data, filters, pagination, sort_by, extensions } =
+        search_nearby(&client, &config_clone, &retry, location, &options).await.map_err(AppError::PoiSearch)?;
+    poi_types::annotate(&mut food_data.pois, lang);
     
     // 构建响应 - 只返回位置信息和美食数据
     Ok(HttpResponse::Ok().json(ApiResponse {
         success: true,
-        message: "Location and food data retrieved successfully".to_string(),
+        code: None,
+        message: i18n::text(lang, "message.location_food").to_string(),
         data: Some(json!({
             "location_info": {
                 "name": config_clone.keywords,
                 "coordinates": {
                     "longitude": location.0,
                     "latitude": location.1
-                }
+                },
+                "candidate": candidate
             },
             "food_data": food_data,
             "search_config": {
+                "city": config_clone.city,
                 "radius": config_clone.food_radius,
                 "max_results": config_clone.max_food_results,
                 "food_types": config_clone.food_types,
-                "sort_by": "distance"
-            }
+                "keyword": config_clone.food_keywords,
+                "sort_by": sort_by.name(),
+                "extensions": extensions
+            },
+            "filters": filters,
+            "pagination": pagination,
+            "attempts": retry.records()
         })),
         error: None,
     }))
@@ -455,12 +548,17 @@ async fn location_food_api(
 async fn ai_recommendation_api(
     app_data: web::Data<AppState>,
     req: web::Json<LocationRequest>,
-) -> Result<HttpResponse, actix_web::Error> {
-    let config = app_data.config.clone();
+    http_req: HttpRequest,
+) -> Result<HttpResponse, AppError> {
+    let lang = Lang::from_request(&http_req, req.lang.as_deref())?;
+    let config = app_data.config.current();
     let client = app_data.client.clone();
     
     // 创建一个可修改的配置副本
     let mut config_clone = (*config).clone();
+    req.apply_overrides(&mut config_clone)?;
+    let template = req.prompt_template(&config_clone, &app_data.prompts)?;
+    let retry = RetryPolicy::for_request(&config_clone, &http_req);
     
     // 使用请求中的位置信息
     if !req.location.is_empty() {
@@ -469,81 +567,186 @@ async fn ai_recommendation_api(
     }
     
     // 获取地点坐标
-    let location = match get_location(&client, &config_clone).await {
-        Ok(loc) => loc,
-        Err(e) => {
-            return Ok(HttpResponse::BadRequest().json(ApiResponse {
-                success: false,
-                message: "Failed to get location coordinates".to_string(),
-                data: None,
-                error: Some(e.to_string()),
-            }));
-        }
-    };
+    let query = location_query(&config_clone.keywords, &req.candidate_id, req.longitude, req.latitude)?;
+    let candidate = resolve_location(&client, &config_clone, &retry, query).await?;
+    let location = (candidate.longitude, candidate.latitude);
+    if req.location.is_empty() {
+        config_clone.keywords = candidate.name.clone();
+    }
     
     // 搜索附近美食
-    let food_data = match search_food(&client, &config_clone, location).await {
-        Ok(data) => data,
-        Err(e) => {
-            return Ok(HttpResponse::InternalServerError().json(ApiResponse {
-                success: false,
-                message: "Failed to search for food".to_string(),
-                data: None,
-                error: Some(e.to_string()),
-            }));
-        }
-    };
+    let options = req.search_options(&config_clone);
+    let FoodSearch { food_data, filters, pagination, sort_by, .. } =
+        search_nearby(&client, &config_clone, &retry, location, &options).await.map_err(AppError::PoiSearch)?;
     
     // 生成AI提示并调用AI进行分析
-    let ai_prompt = generate_ai_prompt(&food_data, &config_clone.keywords);
-    let ai_response = match ask_qwen(&ai_prompt, &config_clone).await {
-        Ok(response) => Some(response),
-        Err(_) => {
-            // 尝试备用模型
-            let mut backup_config = config_clone.clone();
-            backup_config.qwen_model = "qwen-turbo".to_string();
-            
-            match ask_qwen(&ai_prompt, &backup_config).await {
-                Ok(response) => Some(response),
-                Err(_) => None,
-            }
-        }
-    };
+    let structured = req.structured.unwrap_or(false);
+    let ctx = PromptContext::new(&food_data, &config_clone, req.persona.as_deref(), lang, structured, options.filter.open_at());
+    let ai_prompt = app_data.prompts.render_within_budget(&template, ctx, config_clone.prompt_token_budget())?;
+    if structured {
+        let structured = ask_structured(&client, &ai_prompt, &food_data, &config_clone, &retry).await?;
+        return Ok(HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            code: None,
+            message: i18n::text(lang, "message.ai_recommendation").to_string(),
+            data: Some(json!({
+                "recommendation": render(&structured.recommendation, &ai_prompt.sections, lang),
+                "structured": structured.recommendation,
+                "template": ai_prompt.template,
+                "prompt_budget": ai_prompt.budget,
+                "filters": filters,
+                "pagination": pagination,
+                "sort_by": sort_by.name(),
+                "validation": structured.notes,
+                "model": structured.answer.model,
+                "skipped_models": structured.answer.skipped,
+                "usage": structured.answer.usage,
+                "attempts": retry.records()
+            })),
+            error: None,
+        }));
+    }
+
+    let answer = ask_with_fallback(&client, &ai_prompt, &config_clone, &retry).await?;
     
     // 构建响应 - 只返回AI推荐内容
     Ok(HttpResponse::Ok().json(ApiResponse {
-        success: ai_response.is_some(),
-        message: if ai_response.is_some() {
-            "AI recommendation generated successfully".to_string()
-        } else {
-            "Failed to generate AI recommendations".to_string()
-        },
-        data: if let Some(ref recommendation) = ai_response {
-            Some(json!({
-                "recommendation": recommendation
-            }))
-        } else {
-            None
-        },
-        error: if ai_response.is_none() {
-            Some("Failed to get AI response from both primary and backup models".to_string())
-        } else {
-            None
-        },
+        success: true,
+        code: None,
+        message: i18n::text(lang, "message.ai_recommendation").to_string(),
+        data: Some(json!({
+            "recommendation": answer.content,
+            "template": ai_prompt.template,
+            "prompt_budget": ai_prompt.budget,
+            "filters": filters,
+            "pagination": pagination,
+            "sort_by": sort_by.name(),
+            "model": answer.model,
+            "skipped_models": answer.skipped,
+            "usage": answer.usage,
+            "attempts": retry.records()
+        })),
+        error: None,
     }))
 }
 
-// 只返回AI生成的内容API
+// 流式AI推荐接口（Server-Sent Events）
+// 地点解析和美食搜索失败时返回普通的JSON错误响应；开始输出后依次发送
+// meta（地点信息）、reasoning（思考过程，仅 thinking=true）、delta（回答增量）、done（汇总）或 error 事件
+#[post("/api/ai-recommendation/stream")]
+async fn ai_recommendation_stream(
+    app_data: web::Data<AppState>,
+    req: web::Json<LocationRequest>,
+    http_req: HttpRequest,
+) -> Result<impl Responder, AppError> {
+    let lang = Lang::from_request(&http_req, req.lang.as_deref())?;
+    let config = app_data.config.current();
+    let client = app_data.client.clone();
+    
+    // 创建一个可修改的配置副本
+    let mut config_clone = (*config).clone();
+    req.apply_overrides(&mut config_clone)?;
+    let template = req.prompt_template(&config_clone, &app_data.prompts)?;
+    let retry = RetryPolicy::for_request(&config_clone, &http_req);
+    
+    // 使用请求中的位置信息
+    if !req.location.is_empty() {
+        config_clone.keywords = req.location.clone();
+        println!("📍 使用请求位置: {}", config_clone.keywords.green());
+    }
+    
+    // 获取地点坐标
+    let query = location_query(&config_clone.keywords, &req.candidate_id, req.longitude, req.latitude)?;
+    let candidate = resolve_location(&client, &config_clone, &retry, query).await?;
+    let location = (candidate.longitude, candidate.latitude);
+    if req.location.is_empty() {
+        config_clone.keywords = candidate.name.clone();
+    }
+    
+    // 搜索附近美食
+    let options = req.search_options(&config_clone);
+    let FoodSearch { food_data, filters, pagination, sort_by, .. } =
+        search_nearby(&client, &config_clone, &retry, location, &options).await.map_err(AppError::PoiSearch)?;
+    let ctx = PromptContext::new(&food_data, &config_clone, req.persona.as_deref(), lang, false, options.filter.open_at());
+    let ai_prompt = app_data.prompts.render_within_budget(&template, ctx, config_clone.prompt_token_budget())?;
+    let thinking = req.thinking.unwrap_or(false);
+
+    // 无界队列：回调里同步发送增量，AI输出量有限不需要背压
+    let (tx, rx) = mpsc::unbounded_channel::<sse::Event>();
+    let _ = tx.send(sse_event("meta", json!({
+        "location_info": {
+            "name": config_clone.keywords,
+            "coordinates": { "longitude": location.0, "latitude": location.1 },
+            "candidate": candidate
+        },
+        "poi_count": food_data.pois.len(),
+        "template": ai_prompt.template,
+        "prompt_budget": ai_prompt.budget,
+        "filters": filters,
+        "pagination": pagination,
+        "sort_by": sort_by.name()
+    })));
+
+    actix_web::rt::spawn(async move {
+        let on_delta = |delta: StreamDelta| {
+            let event = match delta {
+                StreamDelta::Content(text) => sse_event("delta", json!({ "content": text })),
+                StreamDelta::Reasoning(text) => sse_event("reasoning", json!({ "content": text })),
+            };
+            let _ = tx.send(event);
+        };
+
+        // 客户端断开时丢弃上游请求的 future，连接随之关闭
+        let result = tokio::select! {
+            _ = tx.closed() => {
+                println!("🔌 客户端已断开，取消AI请求");
+                return;
+            }
+            result = ask_stream_with_fallback(&client, &ai_prompt, &config_clone, &retry, thinking, &on_delta) => result,
+        };
+
+        let event = match result {
+            Ok(summary) => sse_event("done", json!({
+                "model": summary.model,
+                "skipped_models": summary.skipped,
+                "usage": summary.usage,
+                "attempts": retry.records()
+            })),
+            Err(e) => sse_event("error", json!({
+                "code": e.code(),
+                "error": e.detail(lang),
+                "attempts": retry.records()
+            })),
+        };
+        let _ = tx.send(event);
+    });
+
+    let events = futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|event| (event, rx))
+    });
+    Ok(Sse::from_infallible_stream(events).with_keep_alive(Duration::from_secs(15)))
+}
+
+fn sse_event(name: &str, data: Value) -> sse::Event {
+    sse::Data::new(data.to_string()).event(name.to_string()).into()
+}
+
+// 只返回AI生成的内容API，出错时返回统一的JSON错误响应
 #[post("/api/ai/content")]
 async fn ai_content_only(
     app_data: web::Data<AppState>,
     req: web::Json<LocationRequest>,
-) -> Result<HttpResponse, actix_web::Error> {
-    let config = app_data.config.clone();
+    http_req: HttpRequest,
+) -> Result<HttpResponse, AppError> {
+    let lang = Lang::from_request(&http_req, req.lang.as_deref())?;
+    let config = app_data.config.current();
     let client = app_data.client.clone();
     
     // 创建一个可修改的配置副本
     let mut config_clone = (*config).clone();
+    req.apply_overrides(&mut config_clone)?;
+    let template = req.prompt_template(&config_clone, &app_data.prompts)?;
+    let retry = RetryPolicy::for_request(&config_clone, &http_req);
     
     // 使用请求中的位置信息
     if !req.location.is_empty() {
@@ -552,121 +755,64 @@ async fn ai_content_only(
     }
     
     // 获取地点坐标
-    let location = match get_location(&client, &config_clone).await {
-        Ok(loc) => loc,
-        Err(e) => {
-            return Ok(HttpResponse::BadRequest().body(format!("获取位置坐标失败: {}", e)));
-        }
-    };
+    let query = location_query(&config_clone.keywords, &req.candidate_id, req.longitude, req.latitude)?;
+    let candidate = resolve_location(&client, &config_clone, &retry, query).await?;
+    let location = (candidate.longitude, candidate.latitude);
+    if req.location.is_empty() {
+        config_clone.keywords = candidate.name.clone();
+    }
     
     // 搜索附近美食
-    let food_data = match search_food(&client, &config_clone, location).await {
-        Ok(data) => data,
-        Err(e) => {
-            return Ok(HttpResponse::InternalServerError().body(format!("搜索美食失败: {}", e)));
-        }
-    };
+    let options = req.search_options(&config_clone);
+    let FoodSearch { food_data, .. } =
+        search_nearby(&client, &config_clone, &retry, location, &options).await.map_err(AppError::PoiSearch)?;
     
     // 生成AI提示并调用AI进行分析
-    let ai_prompt = generate_ai_prompt(&food_data, &config_clone.keywords);
-    let ai_response = match ask_qwen(&ai_prompt, &config_clone).await {
-        Ok(response) => Some(response),
-        Err(_) => {
-            // 尝试备用模型
-            let mut backup_config = config_clone.clone();
-            backup_config.qwen_model = "qwen-turbo".to_string();
-            
-            match ask_qwen(&ai_prompt, &backup_config).await {
-                Ok(response) => Some(response),
-                Err(_) => None,
-            }
-        }
-    };
+    let ctx = PromptContext::new(&food_data, &config_clone, req.persona.as_deref(), lang, false, options.filter.open_at());
+    let ai_prompt = app_data.prompts.render_within_budget(&template, ctx, config_clone.prompt_token_budget())?;
+    let answer = ask_with_fallback(&client, &ai_prompt, &config_clone, &retry).await?;
     
-    // 只返回AI生成的内容
-    match ai_response {
-        Some(content) => Ok(HttpResponse::Ok().content_type("text/plain; charset=utf-8").body(content)),
-        None => Ok(HttpResponse::InternalServerError().body("无法获取AI推荐内容"))
+    // 只返回AI生成的内容，实际使用的模型和 token 用量放在响应头中
+    let mut response = HttpResponse::Ok();
+    response
+        .content_type("text/plain; charset=utf-8")
+        .insert_header(("X-LLM-Model", answer.model));
+    if let Some(usage) = &answer.usage {
+        response
+            .insert_header(("X-LLM-Prompt-Tokens", usage.prompt_tokens.to_string()))
+            .insert_header(("X-LLM-Completion-Tokens", usage.completion_tokens.to_string()));
     }
+    Ok(response.body(answer.content))
 }
 
 // 地图API - 获取指定地点的静态地图
 #[post("/api/map")]
-async fn get_map_api(req: web::Json<MapRequest>, data: web::Data<AppState>) -> Result<HttpResponse, actix_web::Error> {
+async fn get_map_api(http_req: HttpRequest, req: web::Json<MapRequest>, data: web::Data<AppState>) -> Result<HttpResponse, AppError> {
+    let lang = Lang::from_request(&http_req, req.lang.as_deref())?;
     let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let config = data.config.current();
     
     // 获取地点坐标
-    let coordinates = match get_location_for_map(&data.client, &data.config, &req.location).await {
-        Ok(coords) => coords,
-        Err(e) => {
-            let error_response = MapResponse {
-                status: "error".to_string(),
-                map_url: None,
-                location: Some(req.location.clone()),
-                coordinates: None,
-                message: format!("获取地点坐标失败: {}", e),
-                timestamp,
-            };
-            return Ok(HttpResponse::BadRequest().json(error_response));
-        }
-    };
+    let retry = RetryPolicy::for_request(&config, &http_req);
+    let query = location_query(&req.location, &req.candidate_id, req.longitude, req.latitude)?;
+    let candidate = resolve_location(&data.client, &config, &retry, query).await?;
+    let coordinates = (candidate.longitude, candidate.latitude);
     
     // 生成静态地图URL
-    let map_url = generate_static_map_url(&data.config, coordinates, &req);
+    let map_url = generate_static_map_url(&config, coordinates, &req);
     
     let response = MapResponse {
         status: "success".to_string(),
         map_url: Some(map_url),
         location: Some(req.location.clone()),
         coordinates: Some(coordinates),
-        message: "地图生成成功".to_string(),
+        message: i18n::text(lang, "message.map").to_string(),
         timestamp,
     };
     
     Ok(HttpResponse::Ok().json(response))
 }
 
-// 获取地点坐标的辅助函数
-async fn get_location_for_map(client: &Client, config: &Config, location: &str) -> Result<(f64, f64), Box<dyn Error>> {
-    let mut url = reqwest::Url::parse("https://restapi.amap.com/v3/assistant/inputtips")?;
-    url.query_pairs_mut()
-        .append_pair("key", &config.api_key)
-        .append_pair("keywords", location)
-        .append_pair("city", &config.city);
-
-    let response = client.get(url.clone())
-        .header("User-Agent", &format!("{}-map-service", config.username))
-        .send()
-        .await?;
-
-    if !response.status().is_success() {
-        return Err(format!("地图定位API失败: {}", response.status()).into());
-    }
-
-    let body = response.text().await?;
-    let data: Value = serde_json::from_str(&body)?;
-
-    if let Some(tips) = data["tips"].as_array() {
-        if tips.is_empty() {
-            return Err("未找到相关地点".into());
-        }
-
-        // 获取第一个有效位置
-        for tip in tips {
-            if let Some(location) = tip["location"].as_str() {
-                let coords: Vec<&str> = location.split(',').collect();
-                if coords.len() == 2 {
-                    let longitude = coords[0].parse::<f64>()?;
-                    let latitude = coords[1].parse::<f64>()?;
-                    return Ok((longitude, latitude));
-                }
-            }
-        }
-    }
-    
-    Err("无法解析地点坐标".into())
-}
-
 // 生成高德静态地图URL
 fn generate_static_map_url(config: &Config, coordinates: (f64, f64), req: &MapRequest) -> String {
     let (longitude, latitude) = coordinates;
@@ -691,6 +837,98 @@ fn generate_static_map_url(config: &Config, coordinates: (f64, f64), req: &MapRe
     url
 }
 
+// 城市范围内的关键词搜索接口，调用高德 /v3/place/text
+#[post("/api/search")]
+async fn search_api(
+    app_data: web::Data<AppState>,
+    req: web::Json<SearchRequest>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse, AppError> {
+    let lang = Lang::from_request(&http_req, req.lang.as_deref())?;
+    let config = app_data.config.current();
+    let mut config_clone = (*config).clone();
+    req.apply_overrides(&mut config_clone)?;
+    let retry = RetryPolicy::for_request(&config_clone, &http_req);
+
+    let extensions = Extensions::parse(&config_clone.food_extensions).unwrap_or_default();
+    let (mut food_data, pagination) = search_text(&app_data.client, &config_clone, &retry, req.page.unwrap_or(1), extensions)
+        .await
+        .map_err(AppError::PoiSearch)?;
+    println!("{}", format_food_results(&food_data));
+    poi_types::annotate(&mut food_data.pois, lang);
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        code: None,
+        message: Msg::new("message.search").arg("count", food_data.pois.len()).text(lang),
+        data: Some(json!({
+            "food_data": food_data,
+            "search_config": {
+                "keyword": config_clone.food_keywords,
+                "city": config_clone.city,
+                "max_results": config_clone.max_food_results,
+                "food_types": config_clone.food_types,
+                "extensions": extensions
+            },
+            "pagination": pagination,
+            "attempts": retry.records()
+        })),
+        error: None,
+    }))
+}
+
+// POI 详情接口，调用高德 /v3/place/detail
+#[get("/api/poi/{id}")]
+async fn poi_detail_api(
+    app_data: web::Data<AppState>,
+    path: web::Path<String>,
+    query: web::Query<LangQuery>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse, AppError> {
+    let lang = Lang::from_request(&http_req, query.lang.as_deref())?;
+    let id = path.into_inner();
+    if id.is_empty() || id.len() > MAX_POI_ID_LEN || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(AppError::invalid_msg("id", Msg::new("validation.poi_id").arg("value", &id)));
+    }
+    let config = app_data.config.current();
+    let retry = RetryPolicy::for_request(&config, &http_req);
+
+    let mut poi = poi_detail(&app_data.client, &config, &retry, &id)
+        .await
+        .map_err(AppError::PoiDetail)?
+        .ok_or_else(|| AppError::PoiNotFound(id.clone()))?;
+    poi_types::annotate(std::slice::from_mut(&mut poi), lang);
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        code: None,
+        message: i18n::text(lang, "message.poi_detail").to_string(),
+        data: Some(json!({
+            "poi": poi,
+            "attempts": retry.records()
+        })),
+        error: None,
+    }))
+}
+
+// POI 分类编码表查询接口
+#[get("/api/poi-types")]
+async fn poi_types_api(query: web::Query<PoiTypesQuery>, http_req: HttpRequest) -> Result<HttpResponse, AppError> {
+    let lang = Lang::from_request(&http_req, query.lang.as_deref())?;
+    let catalog = poi_types::catalog();
+    let types: Vec<_> = catalog.search(&query.q).into_iter().map(|t| t.category(lang)).collect();
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        code: None,
+        message: Msg::new("message.poi_types").arg("count", types.len()).text(lang),
+        data: Some(json!({
+            "version": catalog.version,
+            "query": query.q,
+            "types": types
+        })),
+        error: None,
+    }))
+}
+
 // 健康检查API
 #[get("/health")]
 async fn health_check() -> Result<HttpResponse, actix_web::Error> {
@@ -702,34 +940,123 @@ async fn health_check() -> Result<HttpResponse, actix_web::Error> {
     })))
 }
 
+// 管理接口 - 重新加载配置（需要 Authorization: Bearer <admin_token>）
+#[post("/admin/reload-config")]
+async fn reload_config_api(http_req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse, AppError> {
+    let lang = Lang::from_request(&http_req, None)?;
+    let admin_token = data.config.current().admin_token.clone();
+    let authorized = !admin_token.is_empty()
+        && http_req
+            .headers()
+            .get("Authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(|token| constant_time_eq(token.as_bytes(), admin_token.as_bytes()))
+            .unwrap_or(false);
+
+    if !authorized {
+        return Err(AppError::Unauthorized);
+    }
+
+    let config = reload_and_log(&data.config, "管理接口")?;
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        code: None,
+        message: i18n::text(lang, "message.config_reloaded").to_string(),
+        data: Some(json!({
+            "city": config.city,
+            "food_radius": config.food_radius,
+            "food_types": config.food_types,
+            "max_food_results": config.max_food_results,
+            "qwen_model": config.qwen_model
+        })),
+        error: None,
+    }))
+}
+
+// 比较令牌时耗时与内容无关，避免通过响应时间逐字节猜出 admin_token（长度不同时直接返回）
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
 // 应用状态结构体
 struct AppState {
-    config: Arc<Config>,
+    config: Arc<ConfigStore>,
     client: Client,
+    prompts: PromptTemplates,   // 启动时加载，修改 prompt_dir 中的模板需要重启
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // 初始化日志
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
-    
-    println!("\n{}{}", "🗺️ 智能地理分析系统 ".bold().blue(), "v3.0".yellow());
-    println!("{}", "=".repeat(40).dimmed());
-    println!("{}", "集成高德地图API + 通义千问AI + Web API".bold());
-    
+
+    let cli = Cli::parse();
+
     // 加载配置
-    let config = match load_config() {
-        Ok(cfg) => Arc::new(cfg),
+    let config = match load_config(&cli) {
+        Ok(cfg) => cfg,
         Err(e) => {
-            println!("❌ 配置加载失败: {}", e);
+            eprintln!("❌ 配置加载失败: {}", e);
             process::exit(1);
         }
     };
+
+    // 只打印配置（密钥脱敏），不启动服务
+    if cli.print_config {
+        match serde_json::to_string_pretty(&config.redacted()) {
+            Ok(text) => println!("{}", text),
+            Err(e) => {
+                eprintln!("❌ 配置序列化失败: {}", e);
+                process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
+    // 消息目录和 POI 分类编码表编译进二进制，启动时确认能正确解析
+    if let Err(e) = check_catalogs().and_then(|_| poi_types::check_catalog()) {
+        eprintln!("❌ {}", e);
+        process::exit(1);
+    }
+
+    // 校验配置，汇总所有错误后一次性输出
+    let report = config.validate();
+    if !report.is_ok() {
+        eprint!("❌ 配置{}", report);
+        process::exit(1);
+    }
+
+    // 加载提示词模板，逐个编译并试渲染，模板有误时拒绝启动
+    let prompts = match PromptTemplates::load(&config.prompt_dir) {
+        Ok(prompts) => prompts,
+        Err(e) => {
+            eprintln!("❌ {}", e);
+            process::exit(1);
+        }
+    };
+    if !prompts.contains(&config.default_template) {
+        eprintln!("❌ 默认提示词模板 \"{}\" 不存在，可选值: {:?}", config.default_template, prompts.names());
+        process::exit(1);
+    }
+    if let Some(Command::ValidateConfig) = cli.command {
+        println!("✅ 配置校验通过");
+        return Ok(());
+    }
+    
+    println!("\n{}{}", "🗺️ 智能地理分析系统 ".bold().blue(), "v3.0".yellow());
+    println!("{}", "=".repeat(40).dimmed());
+    println!("{}", "集成高德地图API + 通义千问AI + Web API".bold());
     
     println!("\n🔧 配置加载成功");
+    match config_path(&cli) {
+        Some(path) => println!("📄 配置文件: {}", path.display().to_string().green()),
+        None => println!("⚠️  未找到配置文件，使用默认配置和环境变量"),
+    }
     println!("👤 用户: {}", config.username.green());
     println!("🏙️ 默认城市: {}", config.city.green());
     println!("🤖 AI模型: {}", config.qwen_model.green());
+    println!("📝 提示词模板: {}（默认 {}）", prompts.names().join(", ").green(), config.default_template);
     
     // 创建HTTP客户端
     let client = match Client::builder()
@@ -744,21 +1071,29 @@ async fn main() -> std::io::Result<()> {
     };
     
     // 创建应用状态
+    let server_config = config.server.clone();
+    let store = Arc::new(ConfigStore::new(config, cli.clone(), prompts.names().to_vec()));
     let app_state = web::Data::new(AppState {
-        config: config.clone(),
+        config: store.clone(),
         client,
+        prompts,
     });
+
+    // 配置热重载：监听配置文件变更和 SIGHUP
+    let _watcher = match spawn_file_watcher(store.clone()) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            println!("⚠️  无法监听配置文件变更: {}", e);
+            None
+        }
+    };
+    spawn_sighup_handler(store);
     
     // 启动Web服务器
-    println!("\n🚀 启动Web API服务...");
-    println!("📡 监听地址: http://127.0.0.1:8080");
-    println!("📍 位置美食API: http://127.0.0.1:8080/api/location-food");
-    println!("🤖 AI推荐API: http://127.0.0.1:8080/api/ai-recommendation");
-    println!("📝 纯文本API: http://127.0.0.1:8080/api/ai/content");
-    println!("🗺️ 地图API: http://127.0.0.1:8080/api/map");
-    println!("🩺 健康检查: http://127.0.0.1:8080/health");
-    
-    HttpServer::new(move || {
+    let json_limit = server_config.json_limit;
+    let payload_limit = server_config.payload_limit;
+
+    let mut server = HttpServer::new(move || {
         // 配置 CORS
         let cors = Cors::default()
             .allowed_origin("http://localhost:5173")  // 允许前端域名
@@ -768,19 +1103,67 @@ async fn main() -> std::io::Result<()> {
             .allowed_origin("http://137.0.0.0:5173")
             .allowed_methods(vec!["GET", "POST", "PUT", "DELETE", "OPTIONS"])
             .allowed_headers(vec!["Content-Type", "Authorization"])
+            .expose_headers(vec!["X-LLM-Model", "X-LLM-Prompt-Tokens", "X-LLM-Completion-Tokens"])
             .max_age(3600);
 
         App::new()
             .app_data(app_state.clone())
+            .app_data(web::JsonConfig::default().limit(json_limit).error_handler(|err, _req| {
+                // 请求体无法解析时同样返回统一的JSON错误响应
+                AppError::invalid_msg("body", Msg::new("validation.invalid_body").arg("detail", err)).into()
+            }))
+            .app_data(web::PayloadConfig::default().limit(payload_limit))
+            .wrap(middleware::from_fn(localize_errors))  // 按请求语言生成错误响应
             .wrap(cors)  // 应用 CORS 中间件
             .wrap(middleware::Logger::default())
+            .service(geocode_api)
             .service(location_food_api)
             .service(ai_recommendation_api)
+            .service(ai_recommendation_stream)
             .service(ai_content_only)
             .service(get_map_api)
+            .service(search_api)
+            .service(poi_detail_api)
+            .service(poi_types_api)
             .service(health_check)
+            .service(reload_config_api)
+    })
+    .keep_alive(match server_config.keep_alive_secs {
+        0 => KeepAlive::Disabled,
+        secs => KeepAlive::Timeout(Duration::from_secs(secs)),
     })
-    .bind("127.0.0.1:8080")?
-    .run()
-    .await
-}
\ No newline at end of file
+    .shutdown_timeout(server_config.shutdown_timeout_secs);
+
+    if let Some(workers) = server_config.workers {
+        server = server.workers(workers);
+    }
+
+    for addr in &server_config.bind {
+        server = match server.bind(addr.as_str()) {
+            Ok(s) => s,
+            Err(e) => {
+                println!("❌ 绑定地址 {} 失败: {}", addr, e);
+                process::exit(1);
+            }
+        };
+    }
+
+    // 打印实际监听的地址（端口为0时由系统分配）
+    println!("\n🚀 启动Web API服务...");
+    for addr in server.addrs() {
+        let base = format!("http://{}", addr);
+        println!("📡 监听地址: {}", base.green());
+        println!("🧭 地理编码API: {}/api/geocode", base);
+        println!("📍 位置美食API: {}/api/location-food", base);
+        println!("🤖 AI推荐API: {}/api/ai-recommendation", base);
+        println!("🌊 流式推荐API: {}/api/ai-recommendation/stream", base);
+        println!("📝 纯文本API: {}/api/ai/content", base);
+        println!("🗺️ 地图API: {}/api/map", base);
+        println!("🔎 关键词搜索API: {}/api/search", base);
+        println!("🏷️ POI详情API: {}/api/poi/{{id}}", base);
+        println!("📋 POI分类API: {}/api/poi-types", base);
+        println!("🩺 健康检查: {}/health", base);
+    }
+
+    server.run().await
+}
@@ -0,0 +1,399 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::amap::Poi;
+use crate::i18n::{self, Lang};
+
+// 高德 POI 分类编码表，编译进二进制；表头的 "# version:" 行为表的版本
+const CATALOG_SOURCE: &str = include_str!("../data/amap_poi_types.csv");
+
+// 分类编码表中的一项：6 位编码，前两位为大类、中间两位为中类、后两位为小类
+#[derive(Debug, Clone)]
+pub struct PoiType {
+    pub code: String,
+    names: [[String; 3]; 2],   // [语言][大类/中类/小类]
+    aliases: Vec<String>,      // 常用叫法，如 "日料"，按名称查找时与小类名称同等对待
+}
+
+// 按语言生成的分类名称，随 API 响应返回
+#[derive(Serialize, Debug, Clone)]
+pub struct PoiCategory {
+    pub code: String,
+    pub big: String,
+    pub mid: String,
+    pub sub: String,
+}
+
+pub struct PoiTypeCatalog {
+    pub version: String,
+    types: Vec<PoiType>,
+    by_code: HashMap<String, usize>,
+}
+
+impl PoiType {
+    fn name(&self, lang: Lang, level: usize) -> &str {
+        match lang {
+            Lang::ZhCn => &self.names[0][level],
+            Lang::En => &self.names[1][level],
+        }
+    }
+
+    // 最具体的名称，例如 "火锅店"
+    pub fn sub(&self, lang: Lang) -> &str {
+        self.name(lang, 2)
+    }
+
+    pub fn category(&self, lang: Lang) -> PoiCategory {
+        PoiCategory {
+            code: self.code.clone(),
+            big: self.name(lang, 0).to_string(),
+            mid: self.name(lang, 1).to_string(),
+            sub: self.name(lang, 2).to_string(),
+        }
+    }
+
+    // 完整分类路径，例如 "餐饮服务 / 中餐厅 / 火锅店"；同名的层级只保留一个
+    pub fn path(&self, lang: Lang) -> String {
+        let mut names: Vec<&str> = Vec::with_capacity(3);
+        for level in 0..3 {
+            let name = self.name(lang, level);
+            if names.last() != Some(&name) {
+                names.push(name);
+            }
+        }
+        names.join(" / ")
+    }
+
+    fn matches_name(&self, name: &str, level: usize) -> bool {
+        self.names.iter().any(|names| names[level].eq_ignore_ascii_case(name))
+            || (level == 2 && self.aliases.iter().any(|alias| alias.eq_ignore_ascii_case(name)))
+    }
+
+    // 小类名称或别名包含 text（已转为小写）
+    fn sub_contains(&self, text: &str) -> bool {
+        self.names
+            .iter()
+            .map(|names| &names[2])
+            .chain(&self.aliases)
+            .any(|name| name.to_lowercase().contains(text))
+    }
+}
+
+impl PoiTypeCatalog {
+    pub fn get(&self, code: &str) -> Option<&PoiType> {
+        self.by_code.get(code).map(|&i| &self.types[i])
+    }
+
+    // 最具体的已收录分类：编码本身，其次所属中类（xxxx00），最后所属大类（xx0000）
+    pub fn resolve(&self, code: &str) -> Option<&PoiType> {
+        if code.len() != 6 || !code.is_ascii() {
+            return None;
+        }
+        self.get(code)
+            .or_else(|| self.get(&format!("{}00", &code[..4])))
+            .or_else(|| self.get(&format!("{}0000", &code[..2])))
+    }
+
+    // 编码是否有效：已收录，或者所属大类只收录到大类一级（表中没有它的中类和小类）
+    pub fn is_known(&self, code: &str) -> bool {
+        if self.get(code).is_some() {
+            return true;
+        }
+        if code.len() != 6 || !code.chars().all(|c| c.is_ascii_digit()) {
+            return false;
+        }
+        let big = &code[..2];
+        self.get(&format!("{}0000", big)).is_some()
+            && !self.types.iter().any(|t| t.code.starts_with(big) && !t.code.ends_with("0000"))
+    }
+
+    // 按名称精确查找（中英文均可，英文不区分大小写）：先匹配小类名称和别名，再匹配大类名称
+    pub fn find_by_name(&self, name: &str) -> Option<&PoiType> {
+        let name = name.trim();
+        self.types
+            .iter()
+            .find(|t| t.matches_name(name, 2))
+            .or_else(|| self.types.iter().find(|t| t.code.ends_with("0000") && t.matches_name(name, 0)))
+    }
+
+    // 模糊搜索：编码前缀或任一语言的名称包含 query，query 为空时返回全部
+    pub fn search(&self, query: &str) -> Vec<&PoiType> {
+        let query = query.trim().to_lowercase();
+        self.types
+            .iter()
+            .filter(|t| {
+                query.is_empty()
+                    || t.code.starts_with(&query)
+                    || t.names.iter().flatten().chain(&t.aliases).any(|name| name.to_lowercase().contains(&query))
+            })
+            .collect()
+    }
+
+    // 把筛选词转换为类型代码：已知的类型代码原样使用，其次按名称精确查找，
+    // 最后取小类名称或别名包含该词的所有分类（已被其中的上级分类包含的不再列出）；都没有时返回空
+    pub fn match_term(&self, term: &str) -> Vec<String> {
+        let term = term.trim();
+        if self.is_known(term) {
+            return vec![term.to_string()];
+        }
+        if let Some(t) = self.find_by_name(term) {
+            return vec![t.code.clone()];
+        }
+        let text = term.to_lowercase();
+        let matched: Vec<&str> = self
+            .types
+            .iter()
+            .filter(|t| t.sub_contains(&text))
+            .map(|t| t.code.as_str())
+            .collect();
+        matched
+            .iter()
+            .filter(|&&code| !matched.iter().any(|&parent| parent != code && covers(parent, code)))
+            .map(|code| code.to_string())
+            .collect()
+    }
+}
+
+pub fn catalog() -> &'static PoiTypeCatalog {
+    static CATALOG: OnceLock<PoiTypeCatalog> = OnceLock::new();
+    CATALOG.get_or_init(|| {
+        // 启动时 check_catalog 已确认能解析，这里解析失败时退化为空表
+        parse_catalog(CATALOG_SOURCE).unwrap_or_else(|_| PoiTypeCatalog {
+            version: String::new(),
+            types: Vec::new(),
+            by_code: HashMap::new(),
+        })
+    })
+}
+
+// 每行：code,大类,中类,小类,big_category,mid_category,sub_category[,别名]；# 开头为注释
+fn parse_catalog(source: &str) -> Result<PoiTypeCatalog, String> {
+    let mut version = None;
+    let mut types = Vec::new();
+    let mut by_code = HashMap::new();
+    for (i, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if let Some(comment) = line.strip_prefix('#') {
+            if let Some(v) = comment.trim().strip_prefix("version:") {
+                version = Some(v.trim().to_string());
+            }
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let (names, aliases) = match fields.len() {
+            7 => (&fields[..], ""),
+            8 => (&fields[..7], fields[7]),
+            n => return Err(format!("第 {} 行应有 7 或 8 列，实际 {} 列", i + 1, n)),
+        };
+        let code = names[0];
+        if code.len() != 6 || !code.chars().all(|c| c.is_ascii_digit()) {
+            return Err(format!("第 {} 行的编码 {:?} 不是 6 位数字", i + 1, code));
+        }
+        if names.iter().any(|f| f.is_empty()) {
+            return Err(format!("第 {} 行有空字段", i + 1));
+        }
+        if by_code.insert(code.to_string(), types.len()).is_some() {
+            return Err(format!("编码 {} 重复", code));
+        }
+        types.push(PoiType {
+            code: code.to_string(),
+            names: [
+                [names[1], names[2], names[3]].map(str::to_string),
+                [names[4], names[5], names[6]].map(str::to_string),
+            ],
+            aliases: aliases.split(';').map(str::trim).filter(|a| !a.is_empty()).map(str::to_string).collect(),
+        });
+    }
+    let version = version.ok_or("缺少 # version: 行")?;
+    Ok(PoiTypeCatalog { version, types, by_code })
+}
+
+// 启动时校验分类编码表能够解析
+pub fn check_catalog() -> Result<(), String> {
+    parse_catalog(CATALOG_SOURCE)
+        .map(|_| ())
+        .map_err(|e| format!("POI 分类编码表解析失败: {}", e))
+}
+
+// 高德的 typecode 可能包含多个编码（如 "050100|080000"），以第一个为准
+fn primary_code(typecode: &str) -> &str {
+    typecode.split('|').next().unwrap_or_default().trim()
+}
+
+// parent 是否包含 code：大类（xx0000）包含其下所有编码，中类（xxxx00）包含其下的小类
+pub fn covers(parent: &str, code: &str) -> bool {
+    if parent == code {
+        return true;
+    }
+    if parent.len() != 6 || code.len() != 6 || !parent.is_ascii() || !code.is_ascii() {
+        return false;
+    }
+    if parent.ends_with("0000") {
+        code.starts_with(&parent[..2])
+    } else {
+        parent.ends_with("00") && code.starts_with(&parent[..4])
+    }
+}
+
+// POI 的 typecode（可能包含多个编码）是否属于 codes 中的任一分类
+pub fn typecode_in(typecode: &str, codes: &[String]) -> bool {
+    typecode
+        .split('|')
+        .map(str::trim)
+        .any(|code| codes.iter().any(|parent| covers(parent, code)))
+}
+
+// 按 POI 的 typecode 查找分类
+pub fn lookup(typecode: &str) -> Option<&'static PoiType> {
+    catalog().resolve(primary_code(typecode))
+}
+
+pub fn category(typecode: &str, lang: Lang) -> Option<PoiCategory> {
+    lookup(typecode).map(|t| t.category(lang))
+}
+
+// 类型的简短说明，表中没有时使用消息目录中的 poi_type.other
+pub fn describe(typecode: &str, lang: Lang) -> String {
+    lookup(typecode)
+        .map(|t| t.sub(lang).to_string())
+        .unwrap_or_else(|| i18n::text(lang, "poi_type.other").to_string())
+}
+
+// 为每个 POI 填入分类名称
+pub fn annotate(pois: &mut [Poi], lang: Lang) {
+    for poi in pois {
+        poi.category = category(&poi.typecode, lang);
+    }
+}
+
+// 把以 | 连接的类型代码或类型名称转换为高德类型代码，无法识别时返回所有无效项
+pub fn resolve_type_codes(types: &str) -> Result<String, Vec<String>> {
+    let catalog = catalog();
+    let mut codes: Vec<String> = Vec::new();
+    let mut invalid: Vec<String> = Vec::new();
+    for item in types.split('|').map(str::trim) {
+        let code = if catalog.is_known(item) {
+            Some(item.to_string())
+        } else {
+            catalog.find_by_name(item).map(|t| t.code.clone())
+        };
+        match code {
+            Some(code) if !codes.contains(&code) => codes.push(code),
+            Some(_) => {}
+            None => invalid.push(item.to_string()),
+        }
+    }
+    if invalid.is_empty() {
+        Ok(codes.join("|"))
+    } else {
+        Err(invalid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embedded_catalog_parses() {
+        assert!(check_catalog().is_ok());
+        let catalog = catalog();
+        assert_eq!(catalog.version, "2024.2");
+        let hotpot = catalog.get("050117").unwrap();
+        assert_eq!(hotpot.sub(Lang::ZhCn), "火锅店");
+        assert_eq!(hotpot.sub(Lang::En), "Hot Pot Restaurant");
+        assert_eq!(hotpot.path(Lang::ZhCn), "餐饮服务 / 中餐厅 / 火锅店");
+        assert_eq!(catalog.get("050100").unwrap().path(Lang::ZhCn), "餐饮服务 / 中餐厅");
+    }
+
+    #[test]
+    fn rejects_malformed_catalogs() {
+        let header = "# version: test\n";
+        let row = "050100,餐饮服务,中餐厅,中餐厅,Food,Chinese,Chinese";
+        assert!(parse_catalog(&format!("{}{}", header, row)).is_ok());
+        assert!(parse_catalog(row).is_err());
+        assert!(parse_catalog(&format!("{}{},x,y", header, row)).is_err());
+        assert!(parse_catalog(&format!("{}05010,餐饮服务,中餐厅,中餐厅,Food,Chinese,Chinese", header)).is_err());
+        assert!(parse_catalog(&format!("{}{}\n{}", header, row, row)).is_err());
+        assert!(parse_catalog(&format!("{}050100,餐饮服务,,中餐厅,Food,Chinese,Chinese", header)).is_err());
+    }
+
+    #[test]
+    fn resolves_to_most_specific_known_type() {
+        let catalog = catalog();
+        let resolved = |code: &str| catalog.resolve(code).map(|t| t.code.as_str());
+        assert_eq!(resolved("050117"), Some("050117"));
+        assert_eq!(resolved("050199"), Some("050100"));
+        assert_eq!(resolved("060101"), Some("060000"));
+        assert_eq!(resolved("0501"), None);
+        assert_eq!(lookup("050117|060000").map(|t| t.code.as_str()), Some("050117"));
+        assert_eq!(describe("210000", Lang::En), "Other");
+    }
+
+    #[test]
+    fn known_codes() {
+        let catalog = catalog();
+        for (code, known) in [
+            ("050117", true),
+            ("050000", true),
+            ("060101", true),    // 购物服务只收录到大类，其下的编码都视为有效
+            ("050199", false),   // 餐饮服务收录到小类，表中没有的编码无效
+            ("210000", false),
+            ("05011", false),
+            ("05011a", false),
+        ] {
+            assert_eq!(catalog.is_known(code), known, "{}", code);
+        }
+    }
+
+    #[test]
+    fn finds_types_by_name_and_alias() {
+        let catalog = catalog();
+        let found = |name: &str| catalog.find_by_name(name).map(|t| t.code.as_str());
+        assert_eq!(found("火锅店"), Some("050117"));
+        assert_eq!(found("火锅"), Some("050117"));
+        assert_eq!(found(" hotpot "), Some("050117"));
+        assert_eq!(found("Hot Pot Restaurant"), Some("050117"));
+        assert_eq!(found("日料"), Some("050202"));
+        assert_eq!(found("餐饮服务"), Some("050000"));
+        assert_eq!(found("Shopping"), Some("060000"));
+        assert_eq!(found("烤鸭"), None);
+    }
+
+    #[test]
+    fn typecode_prefix_matching() {
+        assert!(covers("050000", "050117"));
+        assert!(covers("050100", "050117"));
+        assert!(covers("050117", "050117"));
+        assert!(!covers("050100", "050217"));
+        assert!(!covers("050117", "050118"));
+        assert!(!covers("050000", "060100"));
+        assert!(!covers("0501", "050117"));
+
+        let codes = vec!["050100".to_string()];
+        assert!(typecode_in("060101|050117", &codes));
+        assert!(!typecode_in("050500", &codes));
+    }
+
+    #[test]
+    fn matches_filter_terms() {
+        let catalog = catalog();
+        assert_eq!(catalog.match_term("050117"), ["050117"]);
+        assert_eq!(catalog.match_term("咖啡"), ["050500"]);
+        // 小类名称包含 "料理" 的分类中，外国餐厅（别名 "异国料理"）已包含日本料理和韩国料理
+        assert_eq!(catalog.match_term("料理"), ["050200"]);
+        assert!(catalog.match_term("烧烤").is_empty());
+    }
+
+    #[test]
+    fn resolves_type_codes() {
+        assert_eq!(resolve_type_codes("050100|火锅|Japanese").unwrap(), "050100|050117|050202");
+        assert_eq!(resolve_type_codes("050117|火锅店").unwrap(), "050117");
+        assert_eq!(resolve_type_codes("050100|210000|烤鸭").unwrap_err(), ["210000", "烤鸭"]);
+        assert_eq!(resolve_type_codes("050199").unwrap_err(), ["050199"]);
+    }
+}
@@ -0,0 +1,485 @@
+use chrono::{NaiveDateTime, Timelike};
+use minijinja::{context, Environment, UndefinedBehavior};
+use serde::Serialize;
+use std::{collections::{BTreeMap, BTreeSet}, fs, path::Path};
+
+use crate::amap::PoiSearchResponse;
+use crate::config::Config;
+use crate::error::AppError;
+use crate::hours::{self, OpenState};
+use crate::i18n::{self, Lang, LANGS};
+use crate::llm::ChatMessage;
+use crate::poi_types;
+
+// 内置模板，编译进二进制；prompt_dir 中相同路径的文件会覆盖它们
+const BUILTIN_TEMPLATES: [(&str, &str); 10] = [
+    ("_partials/context.j2", include_str!("../prompts/_partials/context.j2")),
+    ("_partials/output.j2", include_str!("../prompts/_partials/output.j2")),
+    ("default/system.j2", include_str!("../prompts/default/system.j2")),
+    ("default/user.j2", include_str!("../prompts/default/user.j2")),
+    ("business/system.j2", include_str!("../prompts/business/system.j2")),
+    ("business/user.j2", include_str!("../prompts/business/user.j2")),
+    ("student/system.j2", include_str!("../prompts/student/system.j2")),
+    ("student/user.j2", include_str!("../prompts/student/user.j2")),
+    ("date-night/system.j2", include_str!("../prompts/date-night/system.j2")),
+    ("date-night/user.j2", include_str!("../prompts/date-night/user.j2")),
+];
+
+// 模板未设置 first_section / second_section 时使用的推荐分组标题
+const DEFAULT_SECTIONS: [&str; 2] = ["商务聚餐推荐", "学生经济餐厅推荐"];
+
+// 已加载并校验过的提示词模板
+// 每个模板是一个目录，包含 system.j2 和 user.j2；以 _ 开头的目录只存放被 include 的片段
+pub struct PromptTemplates {
+    env: Environment<'static>,
+    names: Vec<String>,
+}
+
+// 模板可用的变量
+#[derive(Serialize, Debug)]
+pub struct PromptContext {
+    pub location: String,
+    pub radius: u32,
+    pub pois: Vec<PoiView>,
+    pub time_of_day: String,   // 早餐/午餐/下午茶/晚餐/夜宵，指定了 open_at 时按该时刻
+    pub now: String,           // 配置的时区中的当前时间
+    pub open_at: String,       // 按营业时间筛选的时刻，未筛选时为空字符串
+    pub persona: String,       // 用户画像，未提供时为空字符串
+    pub structured: bool,      // 是否要求模型输出 JSON
+    pub lang: &'static str,    // 回答语言代码，如 "zh-CN"、"en"
+    pub answer_instruction: String,   // 回答语言要求，简体中文时为空字符串
+    pub type_legend: Vec<TypeLegend>,   // 餐厅列表中出现的类型代码
+}
+
+// 餐厅类型代码说明，desc 为完整分类路径，如 "餐饮服务 / 中餐厅 / 火锅店"
+#[derive(Serialize, Debug, Clone)]
+pub struct TypeLegend {
+    pub code: String,
+    pub desc: String,
+}
+
+// 模板中单个餐厅的字段
+#[derive(Serialize, Debug, Clone)]
+pub struct PoiView {
+    pub index: usize,
+    pub id: String,
+    pub name: String,
+    pub address: Option<String>,
+    pub distance: Option<u32>,
+    pub typecode: String,
+    pub type_desc: String,
+    pub rating: Option<f32>,
+    pub cost: Option<f32>,
+    pub open_time: Option<String>,       // extensions=all 时才有
+    pub business_area: Option<String>,
+    pub tags: Vec<String>,
+    pub open_state: Option<OpenState>,   // "open" 或 "unknown"，只有按营业时间筛选时才有
+}
+
+// 渲染结果；sections 是模板中两个推荐分组的标题，结构化模式渲染文本时使用
+#[derive(Debug)]
+pub struct RenderedPrompt {
+    pub template: String,
+    pub system: String,
+    pub user: String,
+    pub sections: [String; 2],
+    pub budget: PromptBudget,
+}
+
+// 提示词的 token 估算，随响应返回
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct PromptBudget {
+    pub budget_tokens: usize,      // 提示词可用的 token 数，见 Config::prompt_token_budget
+    pub estimated_tokens: usize,   // system + user 的估算值
+    pub system_tokens: usize,
+    pub base_tokens: usize,        // user 提示词中餐厅列表以外的部分
+    pub poi_tokens: usize,         // 餐厅列表
+    pub included_pois: usize,
+    pub dropped_pois: Vec<DroppedPoi>,
+}
+
+// 因超出预算未写入提示词的餐厅
+#[derive(Serialize, Debug, Clone)]
+pub struct DroppedPoi {
+    pub id: String,
+    pub name: String,
+    pub distance: Option<u32>,
+}
+
+impl RenderedPrompt {
+    pub fn messages(&self) -> Vec<ChatMessage> {
+        vec![
+            ChatMessage::new("system", &self.system),
+            ChatMessage::new("user", &self.user),
+        ]
+    }
+}
+
+impl PromptContext {
+    pub fn new(
+        food_data: &PoiSearchResponse,
+        config: &Config,
+        persona: Option<&str>,
+        lang: Lang,
+        structured: bool,
+        open_at: Option<NaiveDateTime>,
+    ) -> Self {
+        let pois = food_data
+            .pois
+            .iter()
+            .enumerate()
+            .map(|(i, poi)| PoiView {
+                index: i + 1,
+                id: poi.id.clone(),
+                name: poi.name.clone(),
+                address: poi.address.clone(),
+                distance: poi.distance,
+                typecode: poi.typecode.clone(),
+                type_desc: poi_types::describe(&poi.typecode, lang),
+                rating: poi.biz_ext.rating,
+                cost: poi.biz_ext.cost,
+                open_time: poi.biz_ext.open_time.clone(),
+                business_area: poi.business_area.clone(),
+                tags: poi.tags.clone(),
+                open_state: poi.open_state,
+            })
+            .collect::<Vec<_>>();
+        let type_legend = type_legend(&pois, lang);
+
+        let now = hours::now(&config.timezone);
+        PromptContext {
+            location: config.keywords.clone(),
+            radius: config.food_radius,
+            pois,
+            time_of_day: time_of_day(open_at.unwrap_or(now).hour()).to_string(),
+            now: now.format("%Y-%m-%d %H:%M").to_string(),
+            open_at: open_at.map(|at| at.format("%Y-%m-%d %H:%M").to_string()).unwrap_or_default(),
+            persona: persona.map(|p| p.trim().to_string()).unwrap_or_default(),
+            structured,
+            lang: lang.code(),
+            answer_instruction: i18n::text(lang, "language.answer_instruction").to_string(),
+            type_legend,
+        }
+    }
+
+    // 启动时校验模板用的示例数据，覆盖可选字段有值和无值两种情况
+    fn sample(lang: Lang, structured: bool) -> Self {
+        let pois = vec![
+            PoiView {
+                index: 1,
+                id: "B000000001".to_string(),
+                name: "示例餐厅".to_string(),
+                address: Some("示例地址".to_string()),
+                distance: Some(100),
+                typecode: "050117".to_string(),
+                type_desc: poi_types::describe("050117", lang),
+                rating: Some(4.5),
+                cost: Some(80.0),
+                open_time: Some("10:00-22:00".to_string()),
+                business_area: Some("示例商圈".to_string()),
+                tags: vec!["牛油锅底".to_string(), "毛肚".to_string()],
+                open_state: Some(OpenState::Open),
+            },
+            PoiView {
+                index: 2,
+                id: "B000000002".to_string(),
+                name: "示例咖啡".to_string(),
+                address: None,
+                distance: None,
+                typecode: "050500".to_string(),
+                type_desc: poi_types::describe("050500", lang),
+                rating: None,
+                cost: None,
+                open_time: None,
+                business_area: None,
+                tags: Vec::new(),
+                open_state: None,
+            },
+        ];
+        PromptContext {
+            location: "示例地点".to_string(),
+            radius: 1000,
+            type_legend: type_legend(&pois, lang),
+            pois,
+            time_of_day: time_of_day(12).to_string(),
+            now: "2024-01-01 12:00".to_string(),
+            open_at: "2024-01-01 12:00".to_string(),
+            persona: "示例用户".to_string(),
+            structured,
+            lang: lang.code(),
+            answer_instruction: i18n::text(lang, "language.answer_instruction").to_string(),
+        }
+    }
+}
+
+impl PromptTemplates {
+    // 加载内置模板和 dir 中的模板，编译并用示例数据试渲染每个模板
+    pub fn load(dir: &str) -> Result<Self, AppError> {
+        let mut sources: BTreeMap<String, String> = BUILTIN_TEMPLATES
+            .iter()
+            .map(|(name, source)| (name.to_string(), source.to_string()))
+            .collect();
+        let dir = Path::new(dir);
+        if dir.is_dir() {
+            read_dir_templates(dir, dir, &mut sources)?;
+        }
+
+        let mut env = Environment::new();
+        env.set_undefined_behavior(UndefinedBehavior::Strict);
+        env.set_trim_blocks(true);
+        env.set_lstrip_blocks(true);
+        for (name, source) in &sources {
+            env.add_template_owned(name.clone(), source.clone())
+                .map_err(|e| AppError::Template(format!("提示词模板 {} 编译失败: {:#}", name, e)))?;
+        }
+
+        let names: Vec<String> = sources
+            .keys()
+            .filter_map(|path| path.strip_suffix("/user.j2"))
+            .filter(|name| !name.starts_with('_') && sources.contains_key(&format!("{}/system.j2", name)))
+            .map(str::to_string)
+            .collect();
+
+        let templates = PromptTemplates { env, names };
+        for name in &templates.names {
+            for lang in LANGS {
+                for structured in [false, true] {
+                    templates.render(name, &PromptContext::sample(lang, structured))?;
+                }
+            }
+        }
+        Ok(templates)
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.iter().any(|n| n == name)
+    }
+
+    // 在 budget 个 token 以内渲染模板：按搜索结果的顺序（已按 sort_by 排序）保留尽可能多的餐厅，其余记录在 dropped_pois 中
+    pub fn render_within_budget(&self, name: &str, mut ctx: PromptContext, budget: usize) -> Result<RenderedPrompt, AppError> {
+        let candidates = std::mem::take(&mut ctx.pois);
+        let legend = std::mem::take(&mut ctx.type_legend);
+        let base = self.render(name, &ctx)?;
+        let system_tokens = estimate_tokens(&base.system);
+        let base_tokens = estimate_tokens(&base.user);
+
+        // 餐厅越多提示词越长：先试全部餐厅，放不下时二分查找能放下的最大数量，只需 O(log n) 次渲染
+        let fits = |rendered: &RenderedPrompt| system_tokens + estimate_tokens(&rendered.user) <= budget;
+        let mut keep = candidates.len();
+        let mut rendered = base;
+        if keep > 0 {
+            let full = self.render_pois(name, &mut ctx, &candidates[..keep], &legend)?;
+            if fits(&full) {
+                rendered = full;
+            } else {
+                // 不变式：前 low 家能放下（low = 0 时为不含餐厅的 base），前 high + 1 家放不下
+                let (mut low, mut high) = (0, keep - 1);
+                while low < high {
+                    let mid = (low + high).div_ceil(2);
+                    let attempt = self.render_pois(name, &mut ctx, &candidates[..mid], &legend)?;
+                    if fits(&attempt) {
+                        low = mid;
+                        rendered = attempt;
+                    } else {
+                        high = mid - 1;
+                    }
+                }
+                keep = low;
+            }
+        }
+
+        let user_tokens = estimate_tokens(&rendered.user);
+        let dropped_pois: Vec<DroppedPoi> = candidates[keep..]
+            .iter()
+            .map(|poi| DroppedPoi {
+                id: poi.id.clone(),
+                name: poi.name.clone(),
+                distance: poi.distance,
+            })
+            .collect();
+        if system_tokens + user_tokens > budget {
+            println!("⚠️  提示词估算 {} tokens，不含餐厅列表也超过预算 {}", system_tokens + user_tokens, budget);
+        } else if !dropped_pois.is_empty() {
+            println!("✂️  提示词预算 {} tokens，保留 {} 家餐厅，省略 {} 家", budget, keep, dropped_pois.len());
+        }
+        rendered.budget = PromptBudget {
+            budget_tokens: budget,
+            estimated_tokens: system_tokens + user_tokens,
+            system_tokens,
+            base_tokens,
+            poi_tokens: user_tokens.saturating_sub(base_tokens),
+            included_pois: keep,
+            dropped_pois,
+        };
+        Ok(rendered)
+    }
+
+    // 只写入 pois 中的餐厅渲染，类型说明只保留这些餐厅用到的
+    fn render_pois(&self, name: &str, ctx: &mut PromptContext, pois: &[PoiView], legend: &[TypeLegend]) -> Result<RenderedPrompt, AppError> {
+        ctx.pois = pois.to_vec();
+        ctx.type_legend = legend
+            .iter()
+            .filter(|item| pois.iter().any(|poi| poi.typecode == item.code))
+            .cloned()
+            .collect();
+        self.render(name, ctx)
+    }
+
+    pub fn render(&self, name: &str, ctx: &PromptContext) -> Result<RenderedPrompt, AppError> {
+        let render_error = |file: &str, e: minijinja::Error| {
+            AppError::Template(format!("提示词模板 {}/{} 渲染失败: {:#}", name, file, e))
+        };
+        let ctx = context! {
+            first_section => DEFAULT_SECTIONS[0],
+            second_section => DEFAULT_SECTIONS[1],
+            ..minijinja::Value::from_serialize(ctx)
+        };
+
+        let system = self
+            .env
+            .get_template(&format!("{}/system.j2", name))
+            .and_then(|t| t.render(&ctx))
+            .map_err(|e| render_error("system.j2", e))?;
+        let captured = self
+            .env
+            .get_template(&format!("{}/user.j2", name))
+            .and_then(|t| t.render_captured(&ctx))
+            .map_err(|e| render_error("user.j2", e))?;
+
+        // 模板可以用 {% set first_section = "..." %} 自定义两个推荐分组的标题
+        let section = |key: &str, default: &str| {
+            captured
+                .state()
+                .lookup(key)
+                .and_then(|v| v.as_str().map(str::to_string))
+                .unwrap_or_else(|| default.to_string())
+        };
+        Ok(RenderedPrompt {
+            template: name.to_string(),
+            system: system.trim().to_string(),
+            user: captured.output().trim().to_string(),
+            sections: [
+                section("first_section", DEFAULT_SECTIONS[0]),
+                section("second_section", DEFAULT_SECTIONS[1]),
+            ],
+            budget: PromptBudget::default(),
+        })
+    }
+}
+
+// 递归读取目录中的 .j2 文件，模板名为相对路径（使用 / 分隔）
+fn read_dir_templates(root: &Path, dir: &Path, sources: &mut BTreeMap<String, String>) -> Result<(), AppError> {
+    let read_error = |path: &Path, e: std::io::Error| AppError::Template(format!("读取提示词模板 {} 失败: {}", path.display(), e));
+    for entry in fs::read_dir(dir).map_err(|e| read_error(dir, e))? {
+        let path = entry.map_err(|e| read_error(dir, e))?.path();
+        if path.is_dir() {
+            read_dir_templates(root, &path, sources)?;
+        } else if path.extension().is_some_and(|ext| ext == "j2") {
+            let source = fs::read_to_string(&path).map_err(|e| read_error(&path, e))?;
+            let name = path
+                .strip_prefix(root)
+                .unwrap_or(&path)
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            sources.insert(name, source);
+        }
+    }
+    Ok(())
+}
+
+// 粗略估算 token 数：中文等非 ASCII 字符约 1 个字 1 个 token，ASCII 约 4 个字符 1 个 token
+// 只用于控制提示词长度，实际用量以模型返回的 usage 为准
+pub fn estimate_tokens(text: &str) -> usize {
+    let ascii = text.chars().filter(char::is_ascii).count();
+    let other = text.chars().count() - ascii;
+    other + ascii.div_ceil(4)
+}
+
+// 餐厅列表中出现的类型代码及其分类路径，按代码排序
+fn type_legend(pois: &[PoiView], lang: Lang) -> Vec<TypeLegend> {
+    let codes: BTreeSet<&str> = pois.iter().map(|poi| poi.typecode.as_str()).collect();
+    codes
+        .into_iter()
+        .map(|code| TypeLegend {
+            code: code.to_string(),
+            desc: poi_types::lookup(code)
+                .map(|t| t.path(lang))
+                .unwrap_or_else(|| i18n::text(lang, "poi_type.other").to_string()),
+        })
+        .collect()
+}
+
+fn time_of_day(hour: u32) -> &'static str {
+    match hour {
+        5..=9 => "早餐",
+        10..=13 => "午餐",
+        14..=16 => "下午茶",
+        17..=20 => "晚餐",
+        _ => "夜宵",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(count: usize) -> PromptContext {
+        let mut ctx = PromptContext::sample(Lang::ZhCn, false);
+        let template = ctx.pois[0].clone();
+        ctx.pois = (1..=count)
+            .map(|i| PoiView {
+                index: i,
+                id: format!("B{:09}", i),
+                name: format!("餐厅{}", i),
+                distance: Some(i as u32 * 10),
+                ..template.clone()
+            })
+            .collect();
+        ctx.type_legend = type_legend(&ctx.pois, Lang::ZhCn);
+        ctx
+    }
+
+    fn tokens(templates: &PromptTemplates, count: usize) -> usize {
+        let rendered = templates.render("default", &context(count)).unwrap();
+        estimate_tokens(&rendered.system) + estimate_tokens(&rendered.user)
+    }
+
+    #[test]
+    fn keeps_everything_within_budget() {
+        let templates = PromptTemplates::load("does-not-exist").unwrap();
+        let rendered = templates.render_within_budget("default", context(20), 1_000_000).unwrap();
+        assert_eq!(rendered.budget.included_pois, 20);
+        assert!(rendered.budget.dropped_pois.is_empty());
+    }
+
+    #[test]
+    fn drops_lowest_ranked_pois() {
+        let templates = PromptTemplates::load("does-not-exist").unwrap();
+        for expected in [0, 1, 7, 19] {
+            // 预算刚好放下前 expected 家
+            let budget = tokens(&templates, expected);
+            let rendered = templates.render_within_budget("default", context(20), budget).unwrap();
+            assert_eq!(rendered.budget.included_pois, expected, "budget {}", budget);
+            assert!(rendered.budget.estimated_tokens <= budget);
+            let dropped: Vec<String> = rendered.budget.dropped_pois.iter().map(|poi| poi.id.clone()).collect();
+            let expected_dropped: Vec<String> = (expected + 1..=20).map(|i| format!("B{:09}", i)).collect();
+            assert_eq!(dropped, expected_dropped);
+            assert!(!rendered.user.contains(&format!("餐厅{}", expected + 1)));
+        }
+    }
+
+    #[test]
+    fn over_budget_without_pois() {
+        let templates = PromptTemplates::load("does-not-exist").unwrap();
+        let rendered = templates.render_within_budget("default", context(3), 10).unwrap();
+        assert_eq!(rendered.budget.included_pois, 0);
+        assert_eq!(rendered.budget.dropped_pois.len(), 3);
+        assert!(rendered.budget.estimated_tokens > 10);
+    }
+}
@@ -0,0 +1,216 @@
+use std::cmp::Ordering;
+
+use crate::amap::Poi;
+use crate::config::RankingConfig;
+
+// 可选的排序方式，请求的 sort_by 和 ranking.default_sort_by 使用这些名称
+pub const SORT_BY_NAMES: [&str; 5] = ["distance", "rating", "cost", "composite", "diversity"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortBy {
+    #[default]
+    Distance,    // 距离由近到远
+    Rating,      // 评分由高到低
+    Cost,        // 人均消费由低到高
+    Composite,   // 按 ranking 中的权重综合距离、评分和人均消费打分
+    Diversity,   // 综合分基础上，同类餐厅每出现一次降低后续同类的分数
+}
+
+impl SortBy {
+    pub fn parse(name: &str) -> Option<SortBy> {
+        match name.trim() {
+            "distance" => Some(SortBy::Distance),
+            "rating" => Some(SortBy::Rating),
+            "cost" => Some(SortBy::Cost),
+            "composite" => Some(SortBy::Composite),
+            "diversity" => Some(SortBy::Diversity),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SortBy::Distance => "distance",
+            SortBy::Rating => "rating",
+            SortBy::Cost => "cost",
+            SortBy::Composite => "composite",
+            SortBy::Diversity => "diversity",
+        }
+    }
+
+    // 除距离外都要用到 biz_ext 中的评分和人均消费，需要高德返回 extensions=all
+    pub fn needs_biz_ext(self) -> bool {
+        self != SortBy::Distance
+    }
+}
+
+// 按 sort_by 对餐厅排序；缺少排序字段的餐厅排在后面，相同时保持原有顺序（高德按距离排序）
+pub fn rank(pois: &mut Vec<Poi>, sort_by: SortBy, radius: u32, config: &RankingConfig) {
+    match sort_by {
+        SortBy::Distance => pois.sort_by(|a, b| missing_last(a.distance, b.distance, |x, y| x.cmp(&y))),
+        SortBy::Rating => pois.sort_by(|a, b| missing_last(a.biz_ext.rating, b.biz_ext.rating, |x, y| y.total_cmp(&x))),
+        SortBy::Cost => pois.sort_by(|a, b| missing_last(a.biz_ext.cost, b.biz_ext.cost, |x, y| x.total_cmp(&y))),
+        SortBy::Composite => {
+            let scores = composite_scores(pois, radius, config);
+            let mut order: Vec<usize> = (0..pois.len()).collect();
+            order.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+            reorder(pois, &order);
+        }
+        SortBy::Diversity => {
+            let order = diversify(pois, &composite_scores(pois, radius, config), config.diversity_penalty);
+            reorder(pois, &order);
+        }
+    }
+}
+
+fn missing_last<T: Copy>(a: Option<T>, b: Option<T>, cmp: impl Fn(T, T) -> Ordering) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => cmp(a, b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+// 综合分：距离越近、评分越高、人均越低分数越高，各项归一化到 0-1，缺少的项按 0.5 计
+fn composite_scores(pois: &[Poi], radius: u32, config: &RankingConfig) -> Vec<f64> {
+    let max_cost = pois
+        .iter()
+        .filter_map(|poi| poi.biz_ext.cost)
+        .fold(0.0_f64, |max, cost| max.max(f64::from(cost)));
+    pois.iter()
+        .map(|poi| {
+            let distance = poi
+                .distance
+                .map(|d| 1.0 - (f64::from(d) / f64::from(radius.max(1))).min(1.0))
+                .unwrap_or(0.5);
+            let rating = poi.biz_ext.rating.map(|r| f64::from(r) / 5.0).unwrap_or(0.5);
+            let cost = match poi.biz_ext.cost {
+                Some(cost) if max_cost > 0.0 => 1.0 - f64::from(cost) / max_cost,
+                _ => 0.5,
+            };
+            config.distance_weight * distance + config.rating_weight * rating + config.cost_weight * cost
+        })
+        .collect()
+}
+
+// 逐个选出调整后分数最高的餐厅，已选出的同类（同一中类，如咖啡厅）每有一家，分数降低 penalty
+fn diversify(pois: &[Poi], scores: &[f64], penalty: f64) -> Vec<usize> {
+    let category = |poi: &Poi| poi.typecode.get(..4).unwrap_or_default().to_string();
+    let categories: Vec<String> = pois.iter().map(category).collect();
+    let mut remaining: Vec<usize> = (0..pois.len()).collect();
+    let mut order: Vec<usize> = Vec::with_capacity(pois.len());
+    while !remaining.is_empty() {
+        let adjusted = |index: usize| {
+            let same = order.iter().filter(|&&i| categories[i] == categories[index]).count();
+            scores[index] - penalty * same as f64
+        };
+        // 分数相同时保留原有顺序靠前（距离更近）的
+        let (position, _) = remaining
+            .iter()
+            .enumerate()
+            .fold((0, f64::MIN), |best, (position, &index)| {
+                let value = adjusted(index);
+                if value > best.1 { (position, value) } else { best }
+            });
+        order.push(remaining.remove(position));
+    }
+    order
+}
+
+fn reorder(pois: &mut Vec<Poi>, order: &[usize]) {
+    let mut taken: Vec<Option<Poi>> = std::mem::take(pois).into_iter().map(Some).collect();
+    *pois = order.iter().filter_map(|&i| taken[i].take()).collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // (id, typecode, distance, rating, cost)
+    type Row = (&'static str, &'static str, Option<u32>, Option<f32>, Option<f32>);
+
+    fn pois(rows: &[Row]) -> Vec<Poi> {
+        rows.iter()
+            .map(|&(id, typecode, distance, rating, cost)| {
+                let mut poi = Poi { id: id.to_string(), typecode: typecode.to_string(), distance, ..Poi::default() };
+                poi.biz_ext.rating = rating;
+                poi.biz_ext.cost = cost;
+                poi
+            })
+            .collect()
+    }
+
+    fn ranked(rows: &[Row], sort_by: SortBy, config: &RankingConfig) -> Vec<String> {
+        let mut pois = pois(rows);
+        rank(&mut pois, sort_by, 1000, config);
+        pois.into_iter().map(|poi| poi.id).collect()
+    }
+
+    fn weights(distance_weight: f64, rating_weight: f64, cost_weight: f64, diversity_penalty: f64) -> RankingConfig {
+        RankingConfig { distance_weight, rating_weight, cost_weight, diversity_penalty, ..RankingConfig::default() }
+    }
+
+    const ROWS: [Row; 5] = [
+        ("a", "050100", Some(300), Some(4.0), Some(80.0)),
+        ("b", "050100", None, Some(4.8), None),
+        ("c", "050500", Some(100), None, Some(30.0)),
+        ("d", "050300", Some(200), Some(3.0), Some(120.0)),
+        ("e", "050100", Some(200), Some(4.0), Some(50.0)),
+    ];
+
+    #[test]
+    fn single_field_orderings() {
+        let config = RankingConfig::default();
+        let cases = [
+            // 缺少字段的排在最后，相同时保持原有顺序
+            (SortBy::Distance, ["c", "d", "e", "a", "b"]),
+            (SortBy::Rating, ["b", "a", "e", "d", "c"]),
+            (SortBy::Cost, ["c", "e", "a", "d", "b"]),
+        ];
+        for (sort_by, expected) in cases {
+            assert_eq!(ranked(&ROWS, sort_by, &config), expected, "{}", sort_by.name());
+        }
+    }
+
+    #[test]
+    fn composite_weights() {
+        let cases = [
+            // 只看距离：缺少距离按 0.5 计，即 500 米
+            (weights(1.0, 0.0, 0.0, 0.0), ["c", "d", "e", "a", "b"]),
+            // 只看评分：缺少评分按 2.5 分计
+            (weights(0.0, 1.0, 0.0, 0.0), ["b", "a", "e", "d", "c"]),
+            // 只看人均：按最高人均归一化，缺少人均按 0.5 计（即 60 元）
+            (weights(0.0, 0.0, 1.0, 0.0), ["c", "e", "b", "a", "d"]),
+        ];
+        for (config, expected) in cases {
+            assert_eq!(ranked(&ROWS, SortBy::Composite, &config), expected);
+        }
+    }
+
+    #[test]
+    fn diversity_penalty() {
+        let rows: [Row; 4] = [
+            ("hotpot1", "050117", Some(100), Some(4.8), None),
+            ("hotpot2", "050117", Some(150), Some(4.7), None),
+            ("hotpot3", "050118", Some(200), Some(4.6), None),
+            ("cafe", "050500", Some(300), Some(4.0), None),
+        ];
+        // 不惩罚时与综合分一致
+        let config = weights(0.0, 1.0, 0.0, 0.0);
+        assert_eq!(ranked(&rows, SortBy::Diversity, &config), ranked(&rows, SortBy::Composite, &config));
+        assert_eq!(ranked(&rows, SortBy::Composite, &config), ["hotpot1", "hotpot2", "hotpot3", "cafe"]);
+
+        // 同一中类（0501）每出现一家，后续同类降低 0.2 分
+        let config = weights(0.0, 1.0, 0.0, 0.2);
+        assert_eq!(ranked(&rows, SortBy::Diversity, &config), ["hotpot1", "cafe", "hotpot2", "hotpot3"]);
+    }
+
+    #[test]
+    fn parses_names() {
+        for name in SORT_BY_NAMES {
+            assert_eq!(SortBy::parse(name).map(SortBy::name), Some(name));
+        }
+        assert_eq!(SortBy::parse("price"), None);
+    }
+}
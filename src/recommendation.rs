@@ -0,0 +1,343 @@
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+
+use crate::amap::{Poi, PoiSearchResponse};
+use crate::config::Config;
+use crate::error::AppError;
+use crate::i18n::{self, Lang, Msg};
+use crate::llm::{chat_with_fallback, ChatMessage, ChatOptions, LlmAnswer};
+use crate::prompt::RenderedPrompt;
+use crate::retry::RetryPolicy;
+
+const MAX_BUSINESS: usize = 3;
+const MAX_STUDENT: usize = 2;
+
+// 结构化推荐，对应提示词中的四个部分；输出格式见 prompts/_partials/output.j2
+// business / student 分别是模板的第一、第二个推荐分组，默认模板中为商务聚餐和学生经济餐厅
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct StructuredRecommendation {
+    #[serde(default)]
+    pub business: Vec<Recommendation>,      // 第一个推荐分组
+    #[serde(default)]
+    pub student: Vec<Recommendation>,       // 第二个推荐分组
+    #[serde(default)]
+    pub location_analysis: String,          // 地理位置分析
+    #[serde(default)]
+    pub summary: String,                    // 综合评价与建议
+}
+
+// 单条推荐，poi_id 对应搜索结果中的 Poi.id；name 和 distance 以搜索结果为准
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Recommendation {
+    #[serde(default)]
+    pub poi_id: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub reason: String,
+    #[serde(default, skip_deserializing)]
+    pub distance: Option<u32>,
+}
+
+// 校验过程中对模型输出做的修改
+#[derive(Serialize, Debug, Default)]
+pub struct ValidationNotes {
+    pub repaired: Vec<String>,   // poi_id 无效但名称能对应到搜索结果，已修正
+    pub rejected: Vec<String>,   // 无法对应到搜索结果，已丢弃
+    pub reasked: bool,           // 第一次输出无法使用，要求模型重新生成过
+}
+
+pub struct StructuredAnswer {
+    pub recommendation: StructuredRecommendation,
+    pub notes: ValidationNotes,
+    pub answer: LlmAnswer,
+}
+
+// 以 JSON 模式请求模型，校验输出；无法解析或没有可用推荐时把问题告诉模型重新生成一次
+pub async fn ask_structured(
+    client: &Client,
+    prompt: &RenderedPrompt,
+    food_data: &PoiSearchResponse,
+    config: &Config,
+    retry: &RetryPolicy,
+) -> Result<StructuredAnswer, AppError> {
+    let options = ChatOptions { json: true };
+    ask_validated(prompt.messages(), food_data, |messages| async move {
+        chat_with_fallback(client, &messages, options, config, retry).await
+    })
+    .await
+}
+
+// 调用 chat 并校验输出，最多重新生成一次；chat 单独传入以便测试时替换模型调用
+async fn ask_validated<F, Fut>(
+    mut messages: Vec<ChatMessage>,
+    food_data: &PoiSearchResponse,
+    chat: F,
+) -> Result<StructuredAnswer, AppError>
+where
+    F: Fn(Vec<ChatMessage>) -> Fut,
+    Fut: Future<Output = Result<LlmAnswer, AppError>>,
+{
+    let answer = chat(messages.clone()).await?;
+
+    let problem = match validate(&answer.content, food_data) {
+        Ok((recommendation, notes)) => return Ok(StructuredAnswer { recommendation, notes, answer }),
+        Err(problem) => problem,
+    };
+    println!("⚠️  结构化输出校验失败: {}，要求模型重新生成", problem);
+
+    let first_usage = answer.usage.clone();
+    messages.push(ChatMessage::new("assistant", &answer.content));
+    messages.push(ChatMessage::new(
+        "user",
+        &format!("你的回答有问题：{}。请严格按照要求重新输出 JSON，poi_id 只能使用列表中的餐厅 ID。", problem),
+    ));
+    let mut answer = chat(messages).await?;
+    // 用量包含两次调用
+    answer.usage = match (first_usage, answer.usage) {
+        (Some(mut total), Some(usage)) => {
+            total.add(&usage);
+            Some(total)
+        }
+        (first, second) => second.or(first),
+    };
+    let (recommendation, mut notes) = validate(&answer.content, food_data)
+        .map_err(|problem| AppError::Llm(format!("结构化输出校验失败: {}", problem)))?;
+    notes.reasked = true;
+    Ok(StructuredAnswer { recommendation, notes, answer })
+}
+
+// 解析并校验模型输出：poi_id 必须来自搜索结果，名称能对应上的修正 ID，其余丢弃
+fn validate(raw: &str, food_data: &PoiSearchResponse) -> Result<(StructuredRecommendation, ValidationNotes), String> {
+    let mut recommendation: StructuredRecommendation =
+        serde_json::from_str(extract_json(raw)).map_err(|e| format!("不是有效的 JSON: {}", e))?;
+    let mut notes = ValidationNotes::default();
+
+    recommendation.business = check_section(&recommendation.business, MAX_BUSINESS, food_data, &mut notes);
+    recommendation.student = check_section(&recommendation.student, MAX_STUDENT, food_data, &mut notes);
+
+    if recommendation.business.is_empty() && recommendation.student.is_empty() && !food_data.pois.is_empty() {
+        return Err("没有任何推荐对应到列出的餐厅 ID".to_string());
+    }
+    Ok((recommendation, notes))
+}
+
+fn check_section(
+    items: &[Recommendation],
+    max: usize,
+    food_data: &PoiSearchResponse,
+    notes: &mut ValidationNotes,
+) -> Vec<Recommendation> {
+    let mut checked: Vec<Recommendation> = Vec::new();
+    for item in items {
+        let poi = match find_poi(food_data, item) {
+            Some(poi) => poi,
+            None => {
+                notes.rejected.push(format!("{} ({})", item.poi_id, item.name));
+                continue;
+            }
+        };
+        // 重复推荐和超出数量的部分直接忽略
+        if checked.iter().any(|c| c.poi_id == poi.id) || checked.len() >= max {
+            continue;
+        }
+        if poi.id != item.poi_id {
+            notes.repaired.push(format!("{} → {} ({})", item.poi_id, poi.id, poi.name));
+        }
+        checked.push(Recommendation {
+            poi_id: poi.id.clone(),
+            name: poi.name.clone(),
+            reason: item.reason.trim().to_string(),
+            distance: poi.distance,
+        });
+    }
+    checked
+}
+
+// 先按 ID 查找，找不到时按名称匹配（模型有时会抄错 ID 但名称正确）
+fn find_poi<'a>(food_data: &'a PoiSearchResponse, item: &Recommendation) -> Option<&'a Poi> {
+    let name = item.name.trim();
+    food_data
+        .pois
+        .iter()
+        .find(|poi| !item.poi_id.is_empty() && poi.id == item.poi_id)
+        .or_else(|| food_data.pois.iter().find(|poi| !name.is_empty() && poi.name == name))
+        .or_else(|| {
+            food_data
+                .pois
+                .iter()
+                .find(|poi| !name.is_empty() && (poi.name.contains(name) || name.contains(poi.name.as_str())))
+        })
+}
+
+// 部分模型即使在 JSON 模式下也会包一层 ```json 代码块
+fn extract_json(raw: &str) -> &str {
+    match (raw.find('{'), raw.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &raw[start..=end],
+        _ => raw.trim(),
+    }
+}
+
+// 渲染为与自由文本模式相同结构的文本，便于直接展示；titles 为模板中两个推荐分组的标题
+pub fn render(recommendation: &StructuredRecommendation, titles: &[String; 2], lang: Lang) -> String {
+    let mut text = String::new();
+    let sections = [
+        (&titles[0], &recommendation.business),
+        (&titles[1], &recommendation.student),
+    ];
+    for (i, (title, items)) in sections.into_iter().enumerate() {
+        text.push_str(&format!("**{}. {}**\n", i + 1, title));
+        if items.is_empty() {
+            text.push_str(&format!("- {}\n", i18n::text(lang, "render.no_recommendation")));
+        }
+        for item in items {
+            let line = match item.distance {
+                Some(distance) => Msg::new("render.item_with_distance").arg("distance", distance),
+                None => Msg::new("render.item"),
+            };
+            text.push_str(&line.arg("name", &item.name).arg("reason", &item.reason).text(lang));
+            text.push('\n');
+        }
+        text.push('\n');
+    }
+    text.push_str(&format!(
+        "**3. {}**\n{}\n\n",
+        i18n::text(lang, "render.location_analysis"),
+        recommendation.location_analysis.trim()
+    ));
+    text.push_str(&format!("**4. {}**\n{}", i18n::text(lang, "render.summary"), recommendation.summary.trim()));
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::Usage;
+    use std::cell::RefCell;
+
+    fn food_data() -> PoiSearchResponse {
+        let poi = |id: &str, name: &str, distance: u32| Poi {
+            id: id.to_string(),
+            name: name.to_string(),
+            distance: Some(distance),
+            ..Poi::default()
+        };
+        PoiSearchResponse {
+            count: 3,
+            pois: vec![
+                poi("B001", "海底捞火锅(国贸店)", 300),
+                poi("B002", "沙县小吃", 120),
+                poi("B003", "麦当劳", 450),
+            ],
+        }
+    }
+
+    fn answer(content: &str, tokens: u32) -> LlmAnswer {
+        LlmAnswer {
+            content: content.to_string(),
+            model: "qwen-plus".to_string(),
+            skipped: Vec::new(),
+            usage: Some(Usage { prompt_tokens: tokens, completion_tokens: tokens, total_tokens: tokens * 2 }),
+        }
+    }
+
+    const VALID: &str = r#"{"business":[{"poi_id":"B001","name":"海底捞","reason":"适合请客"}],
+        "student":[{"poi_id":"B002","name":"沙县小吃","reason":"便宜"}],"location_analysis":"商圈","summary":"不错"}"#;
+
+    #[test]
+    fn keeps_listed_pois_and_uses_search_names() {
+        let (recommendation, notes) = validate(VALID, &food_data()).unwrap();
+        assert_eq!(recommendation.business[0].poi_id, "B001");
+        assert_eq!(recommendation.business[0].name, "海底捞火锅(国贸店)");
+        assert_eq!(recommendation.business[0].distance, Some(300));
+        assert_eq!(recommendation.student[0].reason, "便宜");
+        assert!(notes.repaired.is_empty() && notes.rejected.is_empty() && !notes.reasked);
+    }
+
+    #[test]
+    fn drops_hallucinated_ids_and_repairs_near_misses() {
+        let raw = r#"{"business":[
+                {"poi_id":"B999","name":"不存在的餐厅","reason":"编造的"},
+                {"poi_id":"B0O1","name":"海底捞火锅","reason":"ID 抄错"},
+                {"poi_id":"","name":"麦当劳","reason":"没给 ID"}
+            ],"student":[]}"#;
+        let (recommendation, notes) = validate(raw, &food_data()).unwrap();
+        let ids: Vec<&str> = recommendation.business.iter().map(|r| r.poi_id.as_str()).collect();
+        assert_eq!(ids, ["B001", "B003"]);
+        assert_eq!(notes.rejected, ["B999 (不存在的餐厅)"]);
+        assert_eq!(notes.repaired, ["B0O1 → B001 (海底捞火锅(国贸店))", " → B003 (麦当劳)"]);
+    }
+
+    #[test]
+    fn ignores_duplicates_and_extra_items() {
+        let raw = r#"{"student":[{"poi_id":"B002"},{"poi_id":"B002"},{"poi_id":"B001"},{"poi_id":"B003"}]}"#;
+        let (recommendation, _) = validate(raw, &food_data()).unwrap();
+        let ids: Vec<&str> = recommendation.student.iter().map(|r| r.poi_id.as_str()).collect();
+        assert_eq!(ids, ["B002", "B001"]);
+    }
+
+    #[test]
+    fn rejects_malformed_or_unusable_output() {
+        let fenced = format!("```json\n{}\n```", VALID);
+        assert!(validate(&fenced, &food_data()).is_ok());
+
+        let err = validate("这里是推荐：海底捞", &food_data()).unwrap_err();
+        assert!(err.starts_with("不是有效的 JSON"), "{}", err);
+        assert!(validate(r#"{"business": [}"#, &food_data()).is_err());
+
+        let raw = r#"{"business":[{"poi_id":"B999","name":"不存在的餐厅"}]}"#;
+        assert_eq!(validate(raw, &food_data()).unwrap_err(), "没有任何推荐对应到列出的餐厅 ID");
+        // 搜索结果为空时没有推荐也是有效输出
+        assert!(validate(r#"{"summary":"附近没有餐厅"}"#, &PoiSearchResponse::default()).is_ok());
+    }
+
+    // 依次返回 replies 中的回答，并记录每次调用收到的消息
+    async fn run(replies: &[&str]) -> (Result<StructuredAnswer, AppError>, Vec<Vec<ChatMessage>>) {
+        let calls = RefCell::new(Vec::new());
+        let food_data = food_data();
+        let result = ask_validated(vec![ChatMessage::new("user", "推荐餐厅")], &food_data, |messages| {
+            let reply = replies[calls.borrow().len()];
+            calls.borrow_mut().push(messages);
+            async move { Ok(answer(reply, 10)) }
+        })
+        .await;
+        (result, calls.into_inner())
+    }
+
+    #[actix_rt::test]
+    async fn valid_output_is_not_reasked() {
+        let (result, calls) = run(&[VALID]).await;
+        let structured = result.unwrap();
+        assert_eq!(calls.len(), 1);
+        assert!(!structured.notes.reasked);
+        assert_eq!(structured.answer.usage.unwrap().total_tokens, 20);
+    }
+
+    #[actix_rt::test]
+    async fn reasks_once_with_the_problem() {
+        let (result, calls) = run(&["不是 JSON", VALID]).await;
+        let structured = result.unwrap();
+        assert!(structured.notes.reasked);
+        assert_eq!(structured.recommendation.business[0].poi_id, "B001");
+        assert_eq!(structured.answer.usage.unwrap().total_tokens, 40);
+
+        assert_eq!(calls.len(), 2);
+        let reask = &calls[1];
+        assert_eq!(reask.len(), 3);
+        assert_eq!(reask[1].role, "assistant");
+        assert_eq!(reask[1].content, "不是 JSON");
+        assert_eq!(reask[2].role, "user");
+        assert!(reask[2].content.contains("不是有效的 JSON"));
+    }
+
+    #[actix_rt::test]
+    async fn gives_up_after_one_reask() {
+        let (result, calls) = run(&[r#"{"business":[{"poi_id":"B999"}]}"#, "{}"]).await;
+        assert_eq!(calls.len(), 2);
+        match result {
+            Err(AppError::Llm(message)) => assert!(message.contains("没有任何推荐"), "{}", message),
+            other => panic!("unexpected result: {:?}", other.map(|a| a.recommendation)),
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use colored::*;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::{path::PathBuf, sync::Arc, time::Duration};
+use tokio::sync::mpsc;
+
+use crate::config::{Config, ConfigStore};
+use crate::error::AppError;
+
+// 编辑器保存文件时通常会连续触发多个事件，合并这段时间内的事件只重载一次
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+// 重新加载配置并记录结果，trigger 说明是谁触发的（文件变更 / SIGHUP / 管理接口）
+pub fn reload_and_log(store: &ConfigStore, trigger: &str) -> Result<Arc<Config>, AppError> {
+    match store.reload() {
+        Ok(config) => {
+            println!("🔄 配置已重新加载（{}）", trigger);
+            println!("   🏙️ 默认城市: {} | 📏 半径: {}米 | 🤖 AI模型: {}",
+                     config.city.green(), config.food_radius, config.qwen_model.green());
+            Ok(config)
+        }
+        Err(e) => {
+            println!("❌ 配置重新加载失败（{}），继续使用旧配置: {}", trigger, e);
+            Err(e)
+        }
+    }
+}
+
+// 监听配置文件和密钥文件所在目录，返回的 watcher 需要一直持有，否则监听会停止
+pub fn spawn_file_watcher(store: Arc<ConfigStore>) -> notify::Result<Option<RecommendedWatcher>> {
+    let files: Vec<PathBuf> = store
+        .watched_files()
+        .into_iter()
+        .filter_map(|path| {
+            let dir = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(".".as_ref());
+            Some(dir.canonicalize().ok()?.join(path.file_name()?))
+        })
+        .collect();
+    if files.is_empty() {
+        return Ok(None);
+    }
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<()>();
+    let targets = files.clone();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        if let Ok(event) = res {
+            let relevant = (event.kind.is_modify() || event.kind.is_create())
+                && event.paths.iter().any(|p| targets.contains(p));
+            if relevant {
+                let _ = tx.send(());
+            }
+        }
+    })?;
+
+    // 监听目录而不是文件本身，这样编辑器“写临时文件再改名”的保存方式也能被捕获
+    let mut dirs: Vec<PathBuf> = files.iter().filter_map(|f| f.parent().map(|p| p.to_path_buf())).collect();
+    dirs.dedup();
+    for dir in &dirs {
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    }
+
+    actix_web::rt::spawn(async move {
+        while rx.recv().await.is_some() {
+            tokio::time::sleep(DEBOUNCE).await;
+            while rx.try_recv().is_ok() {}
+            let _ = reload_and_log(&store, "配置文件变更");
+        }
+    });
+
+    for file in &files {
+        println!("👀 监听配置文件变更: {}", file.display());
+    }
+    Ok(Some(watcher))
+}
+
+// 收到 SIGHUP 时重新加载配置
+#[cfg(unix)]
+pub fn spawn_sighup_handler(store: Arc<ConfigStore>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    actix_web::rt::spawn(async move {
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(s) => s,
+            Err(e) => {
+                println!("⚠️  无法注册 SIGHUP 处理: {}", e);
+                return;
+            }
+        };
+        while hangup.recv().await.is_some() {
+            let _ = reload_and_log(&store, "SIGHUP");
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn spawn_sighup_handler(_store: Arc<ConfigStore>) {}
@@ -0,0 +1,266 @@
+use actix_web::{HttpMessage, HttpRequest};
+use rand::Rng;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use serde::Serialize;
+use std::{cell::RefCell, fmt, future::Future, rc::Rc, time::Duration};
+
+use crate::config::Config;
+
+// 能区分临时故障（超时、连接失败、5xx、限流）和永久故障的错误类型，只有临时故障会重试
+pub trait Transient {
+    fn is_transient(&self) -> bool;
+
+    // 服务端通过 Retry-After 指定的等待时间
+    fn retry_after(&self) -> Option<Duration> {
+        None
+    }
+}
+
+// 一次上游调用实际尝试的次数，随响应返回给客户端
+#[derive(Serialize, Debug, Clone)]
+pub struct AttemptRecord {
+    pub call: String,
+    pub attempts: u32,
+}
+
+// 一个请求中所有上游调用的记录，与 RetryPolicy 共享；
+// 放在请求扩展中，请求失败时由 i18n::localize_errors 写入错误响应的 data.attempts
+#[derive(Clone, Default)]
+pub struct AttemptLog(Rc<RefCell<Vec<AttemptRecord>>>);
+
+impl AttemptLog {
+    pub fn records(&self) -> Vec<AttemptRecord> {
+        self.0.borrow().clone()
+    }
+}
+
+// 重试策略：总尝试次数来自 Config.attempts，退避参数来自 Config.retry
+// 每个请求创建一个，clone 出的副本共享调用记录
+#[derive(Clone)]
+pub struct RetryPolicy {
+    attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+    records: AttemptLog,
+}
+
+impl RetryPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        RetryPolicy {
+            attempts: config.attempts.max(1),
+            initial_backoff: Duration::from_millis(config.retry.initial_backoff_ms),
+            max_backoff: Duration::from_millis(config.retry.max_backoff_ms),
+            multiplier: config.retry.multiplier,
+            records: AttemptLog::default(),
+        }
+    }
+
+    // 为请求创建重试策略，并把调用记录放入请求扩展，失败响应同样带上 attempts
+    pub fn for_request(config: &Config, req: &HttpRequest) -> Self {
+        let policy = RetryPolicy::from_config(config);
+        req.extensions_mut().insert(policy.records.clone());
+        policy
+    }
+
+    // 执行 operation，遇到临时故障时按指数退避重试，call 用于日志和调用记录
+    pub async fn run<T, E, F, Fut>(&self, call: &str, mut operation: F) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: Transient + fmt::Display,
+    {
+        let mut attempt = 1;
+        loop {
+            let error = match operation().await {
+                Ok(value) => {
+                    if attempt > 1 {
+                        println!("✅ {} 第 {} 次尝试成功", call, attempt);
+                    }
+                    self.record(call, attempt);
+                    return Ok(value);
+                }
+                Err(e) => e,
+            };
+
+            if !error.is_transient() || attempt >= self.attempts {
+                if attempt > 1 {
+                    println!("❌ {} 共尝试 {} 次后失败: {}", call, attempt, error);
+                }
+                self.record(call, attempt);
+                return Err(error);
+            }
+
+            // 服务端要求的等待时间超过退避上限时直接放弃，不让请求长时间挂起
+            let delay = match error.retry_after() {
+                Some(delay) if delay > self.max_backoff => {
+                    println!("❌ {} 要求 {} 秒后重试，超过退避上限，放弃重试: {}", call, delay.as_secs(), error);
+                    self.record(call, attempt);
+                    return Err(error);
+                }
+                Some(delay) => delay,
+                None => self.backoff(attempt),
+            };
+            println!("🔁 {} 第 {}/{} 次调用失败: {}，{}ms 后重试",
+                     call, attempt, self.attempts, error, delay.as_millis());
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    // 第 n 次失败后的等待时间：initial * multiplier^(n-1)，不超过上限
+    // 在 [delay/2, delay] 之间随机取值，避免并发请求同时重试
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = i32::try_from(attempt - 1).unwrap_or(i32::MAX);
+        let delay = (self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent))
+            .min(self.max_backoff.as_secs_f64());
+        Duration::from_secs_f64(rand::thread_rng().gen_range(delay / 2.0..=delay))
+    }
+
+    fn record(&self, call: &str, attempts: u32) {
+        self.records.0.borrow_mut().push(AttemptRecord {
+            call: call.to_string(),
+            attempts,
+        });
+    }
+
+    pub fn records(&self) -> Vec<AttemptRecord> {
+        self.records.records()
+    }
+}
+
+// Retry-After 可以是秒数，也可以是 HTTP 日期
+pub fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some((date.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RetryConfig;
+    use reqwest::header::HeaderValue;
+    use std::cell::Cell;
+
+    #[derive(Debug)]
+    struct TestError {
+        transient: bool,
+        retry_after: Option<Duration>,
+    }
+
+    impl fmt::Display for TestError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "test error")
+        }
+    }
+
+    impl Transient for TestError {
+        fn is_transient(&self) -> bool {
+            self.transient
+        }
+
+        fn retry_after(&self) -> Option<Duration> {
+            self.retry_after
+        }
+    }
+
+    fn policy(attempts: u32, initial_backoff_ms: u64, max_backoff_ms: u64) -> RetryPolicy {
+        let config = Config {
+            attempts,
+            retry: RetryConfig { initial_backoff_ms, max_backoff_ms, multiplier: 2.0 },
+            ..Config::default()
+        };
+        RetryPolicy::from_config(&config)
+    }
+
+    // 失败 failures 次后成功的操作，返回操作和调用次数
+    async fn run_failing(policy: &RetryPolicy, failures: u32, error: fn() -> TestError) -> (Result<u32, TestError>, u32) {
+        let calls = Cell::new(0);
+        let result = policy
+            .run("test", || async {
+                calls.set(calls.get() + 1);
+                if calls.get() <= failures { Err(error()) } else { Ok(calls.get()) }
+            })
+            .await;
+        (result, calls.get())
+    }
+
+    #[test]
+    fn backoff_grows_with_jitter_and_cap() {
+        let policy = policy(5, 100, 1000);
+        for (attempt, max_ms) in [(1, 100), (2, 200), (3, 400), (4, 800), (5, 1000), (10, 1000)] {
+            for _ in 0..20 {
+                let delay = policy.backoff(attempt).as_secs_f64() * 1000.0;
+                assert!(delay >= max_ms as f64 / 2.0 - 1e-6 && delay <= max_ms as f64 + 1e-6, "attempt {}: {}ms", attempt, delay);
+            }
+        }
+    }
+
+    #[test]
+    fn parses_retry_after() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(parse_retry_after(&headers), None);
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("3"));
+        assert_eq!(parse_retry_after(&headers), Some(Duration::from_secs(3)));
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"));
+        assert_eq!(parse_retry_after(&headers), Some(Duration::ZERO));
+
+        let later = (chrono::Utc::now() + chrono::Duration::seconds(120)).to_rfc2822();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(&later).unwrap());
+        let delay = parse_retry_after(&headers).unwrap();
+        assert!(delay > Duration::from_secs(100) && delay <= Duration::from_secs(120));
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("soon"));
+        assert_eq!(parse_retry_after(&headers), None);
+    }
+
+    #[actix_rt::test]
+    async fn retries_transient_errors() {
+        let policy = policy(3, 1, 5);
+        let (result, calls) = run_failing(&policy, 2, || TestError { transient: true, retry_after: None }).await;
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(calls, 3);
+        assert_eq!(policy.records()[0].attempts, 3);
+    }
+
+    #[actix_rt::test]
+    async fn stops_after_attempts() {
+        let policy = policy(3, 1, 5);
+        let (result, calls) = run_failing(&policy, 5, || TestError { transient: true, retry_after: None }).await;
+        assert!(result.is_err());
+        assert_eq!(calls, 3);
+        assert_eq!(policy.records()[0].attempts, 3);
+    }
+
+    #[actix_rt::test]
+    async fn does_not_retry_permanent_errors() {
+        let policy = policy(3, 1, 5);
+        let (result, calls) = run_failing(&policy, 1, || TestError { transient: false, retry_after: None }).await;
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[actix_rt::test]
+    async fn honors_retry_after() {
+        let policy = policy(3, 1, 50);
+        let (result, calls) =
+            run_failing(&policy, 1, || TestError { transient: true, retry_after: Some(Duration::from_millis(2)) }).await;
+        assert_eq!(result.unwrap(), 2);
+        assert_eq!(calls, 2);
+
+        // 要求的等待时间超过 max_backoff 时不再重试
+        let (result, calls) =
+            run_failing(&policy, 1, || TestError { transient: true, retry_after: Some(Duration::from_secs(60)) }).await;
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+        let records = policy.records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].attempts, 1);
+    }
+}
@@ -0,0 +1,163 @@
+use reqwest::Client;
+
+use crate::amap::{search_food, search_food_pages, AmapError, Extensions, Pagination, PoiSearchResponse};
+use crate::config::{Config, AMAP_MAX_PAGE_SIZE};
+use crate::filter::{AppliedFilters, PoiFilter};
+use crate::ranking::{rank, SortBy};
+use crate::retry::RetryPolicy;
+
+// 请求中与美食搜索有关的选项
+#[derive(Debug, Clone, Default)]
+pub struct SearchOptions {
+    pub filter: PoiFilter,
+    pub page: u32,        // 起始页，从 1 开始
+    pub pages: u32,       // 合并的页数
+    pub sort_by: SortBy,
+    pub extensions: Extensions,
+}
+
+// 美食搜索结果及实际生效的筛选、分页和排序
+pub struct FoodSearch {
+    pub food_data: PoiSearchResponse,
+    pub filters: AppliedFilters,
+    pub pagination: Pagination,
+    pub sort_by: SortBy,
+    pub extensions: Extensions,   // 实际请求高德时使用的 extensions
+}
+
+// 本地筛选时最多请求的高德页数（每页 25 条），避免筛选条件过严时无限翻页
+const POST_FILTER_MAX_AMAP_PAGES: u32 = 10;
+
+// 搜索附近美食：从第 page 页开始合并 pages 页，按筛选条件过滤，再按 sort_by 排序
+// 需要本地筛选时见 search_post_filtered
+pub async fn search_nearby(
+    client: &Client,
+    config: &Config,
+    retry: &RetryPolicy,
+    location: (f64, f64),
+    options: &SearchOptions,
+) -> Result<FoodSearch, AmapError> {
+    let filter = &options.filter;
+    let mut search_config = config.clone();
+    search_config.food_types = filter.amap_types(&config.food_types);
+    let post_filtered = filter.needs_post_filter();
+    // 按评分、人均等排序或按营业时间筛选时，即使请求的是 base 也要取 biz_ext
+    let extensions = if options.sort_by.needs_biz_ext() || filter.open_at().is_some() {
+        Extensions::All
+    } else {
+        options.extensions
+    };
+
+    let (food_data, pagination, filtered_out) = if post_filtered {
+        search_post_filtered(client, &search_config, retry, location, options, extensions).await?
+    } else {
+        let (mut food_data, pagination) =
+            search_food_pages(client, &search_config, retry, location, options.page, options.pages, extensions).await?;
+        rank(&mut food_data.pois, options.sort_by, config.food_radius, &config.ranking);
+        (food_data, pagination, 0)
+    };
+
+    Ok(FoodSearch {
+        food_data,
+        filters: filter.applied(search_config.food_types, post_filtered, filtered_out),
+        pagination,
+        sort_by: options.sort_by,
+        extensions,
+    })
+}
+
+// 本地筛选：筛选后的结果与高德的分页对不上，因此从高德第 1 页起按每页 25 条请求，
+// 直到筛选后的结果足够覆盖客户端请求的第 page 页到第 page + pages - 1 页（每页 max_food_results 条），
+// 排序后按客户端的 page 和 page_size 截取；返回结果、分页信息和筛选掉的数量
+async fn search_post_filtered(
+    client: &Client,
+    config: &Config,
+    retry: &RetryPolicy,
+    location: (f64, f64),
+    options: &SearchOptions,
+    extensions: Extensions,
+) -> Result<(PoiSearchResponse, Pagination, usize), AmapError> {
+    let filter = &options.filter;
+    let page_size = config.max_food_results;
+    let needed = ((options.page + options.pages - 1) * page_size) as usize;
+    let mut amap_config = config.clone();
+    amap_config.max_food_results = AMAP_MAX_PAGE_SIZE;
+
+    let mut food_data = PoiSearchResponse::default();
+    let mut filtered_out = 0;
+    for amap_page in 1..=POST_FILTER_MAX_AMAP_PAGES {
+        let mut data = search_food(client, &amap_config, retry, location, amap_page, extensions).await?;
+        food_data.count = food_data.count.max(data.count);
+        let exhausted = (data.pois.len() as u32) < AMAP_MAX_PAGE_SIZE;
+        filter.mark_open_state(&mut data.pois);
+        for poi in data.pois {
+            if food_data.pois.iter().any(|p| p.id == poi.id) {
+                continue;
+            }
+            if filter.keep(&poi) {
+                food_data.pois.push(poi);
+            } else {
+                filtered_out += 1;
+            }
+        }
+        // 多取一条用于判断是否还有下一页
+        if exhausted || food_data.pois.len() > needed {
+            break;
+        }
+    }
+    println!("🧹 本地筛选去掉 {} 家，保留 {} 家", filtered_out, food_data.pois.len());
+
+    rank(&mut food_data.pois, options.sort_by, config.food_radius, &config.ranking);
+    let has_more = slice_pages(&mut food_data.pois, options.page, options.pages, page_size);
+    let pagination = Pagination::post_filtered(options.page, page_size, options.pages, food_data.count, has_more);
+    Ok((food_data, pagination, filtered_out))
+}
+
+// 保留排序后第 page 页到第 page + pages - 1 页的结果，返回之后是否还有结果
+fn slice_pages<T>(items: &mut Vec<T>, page: u32, pages: u32, page_size: u32) -> bool {
+    let start = ((page - 1) * page_size) as usize;
+    let end = ((page + pages - 1) * page_size) as usize;
+    let has_more = items.len() > end;
+    items.truncate(end);
+    items.drain(..start.min(items.len()));
+    has_more
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slices_requested_pages() {
+        let mut items: Vec<u32> = (1..=30).collect();
+        assert!(slice_pages(&mut items, 2, 1, 5));
+        assert_eq!(items, vec![6, 7, 8, 9, 10]);
+
+        let mut items: Vec<u32> = (1..=30).collect();
+        assert!(slice_pages(&mut items, 2, 3, 5));
+        assert_eq!(items, (6..=20).collect::<Vec<_>>());
+
+        let mut items: Vec<u32> = (1..=12).collect();
+        assert!(!slice_pages(&mut items, 3, 1, 5));
+        assert_eq!(items, vec![11, 12]);
+
+        let mut items: Vec<u32> = (1..=10).collect();
+        assert!(!slice_pages(&mut items, 2, 1, 5));
+        assert_eq!(items, vec![6, 7, 8, 9, 10]);
+
+        let mut items: Vec<u32> = (1..=4).collect();
+        assert!(!slice_pages(&mut items, 3, 1, 5));
+        assert!(items.is_empty());
+    }
+
+    #[test]
+    fn post_filtered_pagination_uses_client_page_size() {
+        let pagination = Pagination::post_filtered(2, 5, 1, 114, true);
+        assert_eq!(pagination.total_pages, 23);
+        assert_eq!(pagination.next_page, Some(3));
+
+        let pagination = Pagination::post_filtered(2, 5, 2, 114, false);
+        assert!(!pagination.has_more);
+        assert_eq!(pagination.next_page, None);
+    }
+}